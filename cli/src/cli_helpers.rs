@@ -1,7 +1,10 @@
 use clap::ArgMatches;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::process;
 use std::str::FromStr;
 use strum_macros::EnumString;
+use swipy_engine::v_function::{Weights, WeightsFormat};
+use swipy_engine::{Board, Replacement};
 
 pub fn parse_arg<T>(matches: &ArgMatches, name: &str) -> T
 where
@@ -11,11 +14,44 @@ where
     matches.value_of(name).unwrap().parse::<T>().unwrap()
 }
 
+/// Prints an error and exits
+pub fn exit_with_error(error: impl Display) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(1)
+}
+
+/// Loads the weights at `path`, or the optimized weights when no path is given
+pub fn load_weights<W: Weights>(path: Option<&str>) -> W {
+    match path {
+        Some(path) => W::load(path).unwrap_or_else(|err| exit_with_error(err)),
+        None => W::optimized().unwrap_or_else(|| {
+            exit_with_error(format!(
+                "no trained weights are shipped for {}, pass them with --weights",
+                W::name()
+            ))
+        }),
+    }
+}
+
+/// Reads a board given in the grid notation, such as `2 4 . ./8 2 . ./. . . ./. . . 2`, or as a
+/// hexadecimal bitboard, such as `0x0000000000012010`
+pub fn parse_board(text: &str) -> Result<Board, String> {
+    if text.contains('/') {
+        return text.parse::<Board>().map_err(|err| err.to_string());
+    }
+
+    u64::from_str_radix(text.trim_start_matches("0x"), 16)
+        .map(Board::from_u64)
+        .map_err(|err| format!("invalid bitboard {}: {}", text, err))
+}
+
 #[derive(Debug, Clone, Copy, EnumString)]
 #[strum(serialize_all = "snake_case")]
 pub enum OutputFormat {
     Human,
     Json,
+    /// Only supported by the commands that output tables
+    Csv,
 }
 
 impl OutputFormat {
@@ -24,16 +60,114 @@ impl OutputFormat {
     }
 }
 
+#[derive(Debug, Clone, Copy, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum WeightsFormatChoice {
+    Json,
+    Binary,
+    Compressed,
+}
+
+impl WeightsFormatChoice {
+    pub fn possible_values() -> &'static [&'static str] {
+        &["json", "binary", "compressed"]
+    }
+
+    pub fn into_weights_format(self) -> WeightsFormat {
+        match self {
+            WeightsFormatChoice::Json => WeightsFormat::Json,
+            WeightsFormatChoice::Binary => WeightsFormat::Binary,
+            WeightsFormatChoice::Compressed => WeightsFormat::CompressedBinary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ReplacementChoice {
+    Always,
+    DepthPreferred,
+    TwoTier,
+}
+
+impl ReplacementChoice {
+    pub fn possible_values() -> &'static [&'static str] {
+        &["always", "depth_preferred", "two_tier"]
+    }
+
+    pub fn into_replacement(self) -> Replacement {
+        match self {
+            ReplacementChoice::Always => Replacement::Always,
+            ReplacementChoice::DepthPreferred => Replacement::DepthPreferred,
+            ReplacementChoice::TwoTier => Replacement::TwoTier,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, EnumString)]
 #[strum(serialize_all = "snake_case")]
 pub enum VFunctionChoice {
     Legacy,
     NTupleSmall,
     NTupleMedium,
+    NTupleNetwork,
+    MultiStageNTupleNetwork,
 }
 
 impl VFunctionChoice {
     pub fn possible_values() -> &'static [&'static str] {
-        &["legacy", "n_tuple_small", "n_tuple_medium"]
+        &[
+            "legacy",
+            "n_tuple_small",
+            "n_tuple_medium",
+            "n_tuple_network",
+            "multi_stage_n_tuple_network",
+        ]
     }
 }
+
+/// Calls the generic function `$function` with the v-function selected by `$choice`, or with the
+/// two v-functions selected by `$choice_a` and `$choice_b`
+macro_rules! with_v_function {
+    (@with $a:ty, $choice:expr, $function:ident($($arg:expr),*)) => {
+        match $choice {
+            VFunctionChoice::Legacy => $function::<$a, Legacy>($($arg),*),
+            VFunctionChoice::NTupleSmall => $function::<$a, NTupleSmall>($($arg),*),
+            VFunctionChoice::NTupleMedium => $function::<$a, NTupleMedium>($($arg),*),
+            VFunctionChoice::NTupleNetwork => $function::<$a, NTupleNetwork>($($arg),*),
+            VFunctionChoice::MultiStageNTupleNetwork => {
+                $function::<$a, MultiStage<NTupleNetwork>>($($arg),*)
+            }
+        }
+    };
+    ($choice_a:expr, $choice_b:expr, $function:ident($($arg:expr),*)) => {
+        match $choice_a {
+            VFunctionChoice::Legacy => {
+                with_v_function!(@with Legacy, $choice_b, $function($($arg),*))
+            }
+            VFunctionChoice::NTupleSmall => {
+                with_v_function!(@with NTupleSmall, $choice_b, $function($($arg),*))
+            }
+            VFunctionChoice::NTupleMedium => {
+                with_v_function!(@with NTupleMedium, $choice_b, $function($($arg),*))
+            }
+            VFunctionChoice::NTupleNetwork => {
+                with_v_function!(@with NTupleNetwork, $choice_b, $function($($arg),*))
+            }
+            VFunctionChoice::MultiStageNTupleNetwork => {
+                with_v_function!(@with MultiStage<NTupleNetwork>, $choice_b, $function($($arg),*))
+            }
+        }
+    };
+    ($choice:expr, $function:ident($($arg:expr),*)) => {
+        match $choice {
+            VFunctionChoice::Legacy => $function::<Legacy>($($arg),*),
+            VFunctionChoice::NTupleSmall => $function::<NTupleSmall>($($arg),*),
+            VFunctionChoice::NTupleMedium => $function::<NTupleMedium>($($arg),*),
+            VFunctionChoice::NTupleNetwork => $function::<NTupleNetwork>($($arg),*),
+            VFunctionChoice::MultiStageNTupleNetwork => {
+                $function::<MultiStage<NTupleNetwork>>($($arg),*)
+            }
+        }
+    };
+}
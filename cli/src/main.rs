@@ -1,37 +1,112 @@
+#[macro_use]
 mod cli_helpers;
+mod render;
 
-use crate::cli_helpers::{parse_arg, OutputFormat, VFunctionChoice};
-use clap::{crate_authors, crate_version, App, AppSettings, Arg, SubCommand};
+use crate::cli_helpers::{
+    exit_with_error, load_weights, parse_arg, parse_board, OutputFormat, ReplacementChoice,
+    VFunctionChoice, WeightsFormatChoice,
+};
+use crate::render::{render_board, render_stats};
+use clap::{crate_authors, crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
 use indicatif::{ProgressBar, ProgressStyle};
+use rand::random;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 use swipy_engine::{
-    testing::{benchmark, play_random_game},
-    train_td,
+    resume_td,
+    testing::{self, benchmark, play_random_game, play_recorded_game, CompareConfig, Verdict},
     v_function::{
-        Legacy, LegacyWeights, NTupleMedium, NTupleMediumWeights, NTupleSmall, NTupleSmallWeights,
-        VFunction, Weights,
+        read_v_function, Legacy, MultiStage, NTupleMedium, NTupleNetwork, NTupleSmall, VFunction,
+        Weights,
     },
-    Engine,
+    AdaptiveDepth, Board, Checkpoint, CheckpointConfig, Engine, EngineConfig, Game, Recording,
+    SearchLimit, SearchStats, TrainingConfig, TrainingState, MAX_EXPONENT,
 };
 
 const DEFAULT_DEPTH: &str = "3";
 const DEFAULT_LEARNING_RATE: &str = "0.0005";
 const DEFAULT_BENCHMARK_INTERVAL: &str = "5000";
+const DEFAULT_CHECKPOINT_INTERVAL: &str = "50000";
+const DEFAULT_COMPARE_MARGIN: &str = "1000";
+const DEFAULT_TABLE_SIZE: &str = "4";
+const DEFAULT_PROB_CUTOFF: &str = "0";
 
 fn init_clap<'a, 'b>() -> App<'a, 'b> {
     let v_function = Arg::with_name("v_function")
         .long("v_function")
         .takes_value(true)
         .default_value("legacy")
-        .possible_values(&VFunctionChoice::possible_values())
+        .possible_values(VFunctionChoice::possible_values())
         .help("The V-function that will be trained and used");
 
     let format = Arg::with_name("format")
         .long("format")
         .takes_value(true)
         .default_value("human")
-        .possible_values(&OutputFormat::possible_values())
+        .possible_values(OutputFormat::possible_values())
         .help("The format of the output");
 
+    let weights = Arg::with_name("weights")
+        .long("weights")
+        .takes_value(true)
+        .help("A weights file for the V-function (the optimized weights if omitted)");
+
+    let seed = Arg::with_name("seed")
+        .long("seed")
+        .takes_value(true)
+        .help("The seed used to generate tile spawns (random if omitted)");
+
+    let time_per_move = Arg::with_name("time-per-move")
+        .long("time-per-move")
+        .takes_value(true)
+        .help("Milliseconds of iterative deepening search per move (overrides --depth)");
+
+    let min_depth = Arg::with_name("min-depth")
+        .long("min-depth")
+        .takes_value(true)
+        .conflicts_with("time-per-move")
+        .help(
+            "Chooses the depth of every move from how crowded the board is, between this depth \
+             and --depth",
+        );
+
+    let threads = Arg::with_name("threads")
+        .long("threads")
+        .takes_value(true)
+        .default_value("1")
+        .help("The number of threads used by the search");
+
+    let canonical_keys = Arg::with_name("canonical-keys")
+        .long("canonical-keys")
+        .help("Shares the transposition table entries of the rotations and reflections of a board");
+
+    let table_size = Arg::with_name("table-size")
+        .long("table-size")
+        .takes_value(true)
+        .default_value(DEFAULT_TABLE_SIZE)
+        .help("The memory taken by the transposition table, in megabytes");
+
+    let replacement = Arg::with_name("replacement")
+        .long("replacement")
+        .takes_value(true)
+        .default_value("two_tier")
+        .possible_values(ReplacementChoice::possible_values())
+        .help("Which evaluation keeps a slot of the transposition table when two boards collide");
+
+    let prob_cutoff = Arg::with_name("prob-cutoff")
+        .long("prob-cutoff")
+        .takes_value(true)
+        .default_value(DEFAULT_PROB_CUTOFF)
+        .help(
+            "Evaluates statically the positions less likely than this to be reached, such as \
+             0.001 (0 searches every position)",
+        );
+
+    let color = Arg::with_name("color")
+        .long("color")
+        .help("Colors the tiles of the boards");
+
     let play = SubCommand::with_name("play")
         .about("plays one game, logging the board to the command line")
         .arg(
@@ -42,7 +117,23 @@ fn init_clap<'a, 'b>() -> App<'a, 'b> {
                 .default_value(DEFAULT_DEPTH)
                 .help("The expectimax search depth"),
         )
-        .arg(&v_function);
+        .arg(&time_per_move)
+        .arg(&min_depth)
+        .arg(&threads)
+        .arg(&canonical_keys)
+        .arg(&table_size)
+        .arg(&replacement)
+        .arg(&prob_cutoff)
+        .arg(&v_function)
+        .arg(&weights)
+        .arg(&seed)
+        .arg(&color)
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("Saves the game to this file so that it can be replayed"),
+        );
     let bench = SubCommand::with_name("bench")
         .about("plays N games to test the strength of the AI")
         .arg(
@@ -59,7 +150,26 @@ fn init_clap<'a, 'b>() -> App<'a, 'b> {
                 .default_value(DEFAULT_DEPTH)
                 .help("The expectimax search depth"),
         )
-        .arg(&v_function);
+        .arg(&time_per_move)
+        .arg(&min_depth)
+        .arg(
+            threads
+                .clone()
+                .help("The number of games played in parallel"),
+        )
+        .arg(&canonical_keys)
+        .arg(&table_size)
+        .arg(&replacement)
+        .arg(&prob_cutoff)
+        .arg(&v_function)
+        .arg(&weights)
+        .arg(
+            format
+                .clone()
+                .possible_value("csv")
+                .help("The format of the output, csv listing every game"),
+        )
+        .arg(&seed);
     let train = SubCommand::with_name("train")
         .about("continuously plays to optimize the AI")
         .arg(
@@ -75,9 +185,24 @@ fn init_clap<'a, 'b>() -> App<'a, 'b> {
                 .default_value(DEFAULT_LEARNING_RATE)
                 .help("The learning rate"),
         )
+        .arg(
+            Arg::with_name("lambda")
+                .long("lambda")
+                .takes_value(true)
+                .default_value("0")
+                .help("The trace decay of TD(λ), between 0 and 1, 0 being TD(0)"),
+        )
+        .arg(
+            Arg::with_name("temporal-coherence")
+                .long("temporal-coherence")
+                .help(
+                    "Adapts the learning rate of each weight with Jaśkowski's delayed temporal \
+                     coherence, alpha scaling the adapted rates",
+                ),
+        )
         .arg(
             Arg::with_name("N")
-                .help("The amount of batches of 5 games to play")
+                .help("The number of games to play")
                 .required(true)
                 .takes_value(true),
         )
@@ -89,15 +214,182 @@ fn init_clap<'a, 'b>() -> App<'a, 'b> {
                 .default_value(DEFAULT_BENCHMARK_INTERVAL)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("Saves the trained weights to this file instead of printing them"),
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .takes_value(true)
+                .help("Regularly saves the state of the run to this file (the resumed file by default)"),
+        )
+        .arg(
+            Arg::with_name("checkpoint-interval")
+                .long("checkpoint-interval")
+                .takes_value(true)
+                .default_value(DEFAULT_CHECKPOINT_INTERVAL)
+                .help("The number of games between two checkpoints"),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .takes_value(true)
+                .conflicts_with_all(&["zero", "weights"])
+                .help(
+                    "Continues the run saved in this checkpoint with its own settings, \
+                     until N games are played",
+                ),
+        )
+        .arg(
+            threads
+                .clone()
+                .help("The number of self-play games played in parallel on the same weights"),
+        )
+        .arg(&v_function)
+        .arg(&weights)
+        .arg(&format)
+        .arg(&seed);
+
+    let compare = SubCommand::with_name("compare")
+        .about("plays two engines on the same games until one is significantly stronger")
+        .arg(
+            Arg::with_name("N")
+                .help("The maximum amount of games played by each engine")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("v_function_a")
+                .long("v_function_a")
+                .takes_value(true)
+                .default_value("legacy")
+                .possible_values(VFunctionChoice::possible_values())
+                .help("The V-function of engine A"),
+        )
+        .arg(
+            Arg::with_name("weights_a")
+                .long("weights_a")
+                .takes_value(true)
+                .help("A weights file for engine A (the optimized weights if omitted)"),
+        )
+        .arg(
+            Arg::with_name("depth_a")
+                .long("depth_a")
+                .takes_value(true)
+                .default_value(DEFAULT_DEPTH)
+                .help("The expectimax search depth of engine A"),
+        )
+        .arg(
+            Arg::with_name("v_function_b")
+                .long("v_function_b")
+                .takes_value(true)
+                .default_value("legacy")
+                .possible_values(VFunctionChoice::possible_values())
+                .help("The V-function of engine B"),
+        )
+        .arg(
+            Arg::with_name("weights_b")
+                .long("weights_b")
+                .takes_value(true)
+                .help("A weights file for engine B (the optimized weights if omitted)"),
+        )
+        .arg(
+            Arg::with_name("depth_b")
+                .long("depth_b")
+                .takes_value(true)
+                .default_value(DEFAULT_DEPTH)
+                .help("The expectimax search depth of engine B"),
+        )
+        .arg(
+            Arg::with_name("margin")
+                .long("margin")
+                .takes_value(true)
+                .default_value(DEFAULT_COMPARE_MARGIN)
+                .help("The smallest average score difference worth detecting"),
+        )
+        .arg(
+            threads
+                .clone()
+                .help("The number of games played in parallel"),
+        )
+        .arg(&canonical_keys)
+        .arg(&table_size)
+        .arg(&replacement)
+        .arg(&prob_cutoff)
+        .arg(&format)
+        .arg(&seed);
+
+    let analyze = SubCommand::with_name("analyze")
+        .about("evaluates every move of a position")
+        .arg(
+            Arg::with_name("BOARD")
+                .help(
+                    "The position, such as \"2 4 . ./8 2 . ./. . . ./. . . 2\" with the rows \
+                     from top to bottom, or a hexadecimal bitboard",
+                )
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("depth")
+                .long("depth")
+                .takes_value(true)
+                .default_value(DEFAULT_DEPTH)
+                .help("The expectimax search depth"),
+        )
+        .arg(&threads)
+        .arg(&canonical_keys)
+        .arg(&table_size)
+        .arg(&replacement)
+        .arg(&prob_cutoff)
         .arg(&v_function)
-        .arg(&format);
+        .arg(&weights)
+        .arg(&format)
+        .arg(&color);
+
+    let replay = SubCommand::with_name("replay")
+        .about("steps through a game saved with play --record")
+        .arg(
+            Arg::with_name("FILE")
+                .help("The recorded game")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("step")
+                .long("step")
+                .help("Waits for enter to be pressed before every move"),
+        )
+        .arg(&color);
+
+    let convert = SubCommand::with_name("convert")
+        .about("converts a weights file to another format")
+        .arg(
+            Arg::with_name("INPUT")
+                .help("The weights file to convert")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The file to write the converted weights to")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .default_value("binary")
+                .possible_values(WeightsFormatChoice::possible_values())
+                .help("The format of the converted file"),
+        );
 
     App::new("Swipy - 2048 AI")
         .author(crate_authors!(", "))
         .version(crate_version!())
         .about("A 2048 AI")
         .setting(AppSettings::ArgRequiredElseHelp)
-        .subcommands(vec![play, bench, train])
+        .subcommands(vec![play, bench, train, compare, analyze, replay, convert])
 }
 
 fn main() {
@@ -107,90 +399,403 @@ fn main() {
     match matches.subcommand_name().unwrap() {
         "play" => {
             let subcommand_matches = matches.subcommand_matches("play").unwrap();
-            let depth = parse_arg::<u8>(subcommand_matches, "depth");
+            let limit = parse_search_limit(subcommand_matches);
+            let config = parse_engine_config(subcommand_matches);
             let v_function = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function");
+            let weights = subcommand_matches.value_of("weights");
+            let seed = parse_seed(subcommand_matches);
+            let record = subcommand_matches.value_of("record");
+            let color = subcommand_matches.is_present("color");
 
-            match v_function {
-                VFunctionChoice::Legacy => play(
-                    &mut Engine::<Legacy>::new(LegacyWeights::optimized()),
-                    depth,
-                ),
-                VFunctionChoice::NTupleSmall => play(
-                    &mut Engine::<NTupleSmall>::new(NTupleSmallWeights::optimized()),
-                    depth,
-                ),
-                VFunctionChoice::NTupleMedium => play(
-                    &mut Engine::<NTupleMedium>::new(NTupleMediumWeights::optimized()),
-                    depth,
-                ),
-            };
+            with_v_function!(
+                v_function,
+                play(weights, config, limit, seed, record, color)
+            );
         }
         "bench" => {
             let subcommand_matches = matches.subcommand_matches("bench").unwrap();
             let num_games = parse_arg::<u64>(subcommand_matches, "N");
-            let depth = parse_arg::<u8>(subcommand_matches, "depth");
+            if num_games == 0 {
+                exit_with_error("the number of games must be at least 1");
+            }
+            let limit = parse_search_limit(subcommand_matches);
+            let config = parse_engine_config(subcommand_matches);
             let v_function = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function");
+            let weights = subcommand_matches.value_of("weights");
+            let seed = parse_seed(subcommand_matches);
+            let format = parse_arg::<OutputFormat>(subcommand_matches, "format");
 
-            match v_function {
-                VFunctionChoice::Legacy => bench(
-                    &mut Engine::<Legacy>::new(LegacyWeights::optimized()),
-                    num_games,
-                    depth,
-                ),
-                VFunctionChoice::NTupleSmall => bench(
-                    &mut Engine::<NTupleSmall>::new(NTupleSmallWeights::optimized()),
-                    num_games,
-                    depth,
-                ),
-                VFunctionChoice::NTupleMedium => bench(
-                    &mut Engine::<NTupleMedium>::new(NTupleMediumWeights::optimized()),
-                    num_games,
-                    depth,
-                ),
-            };
+            with_v_function!(
+                v_function,
+                bench(weights, config, num_games, limit, seed, format)
+            );
         }
         "train" => {
             let subcommand_matches = matches.subcommand_matches("train").unwrap();
-            let num_batches = parse_arg::<u64>(subcommand_matches, "N");
+            let num_games = parse_arg::<u64>(subcommand_matches, "N");
             let zero = subcommand_matches.is_present("zero");
             let alpha = parse_arg::<f32>(subcommand_matches, "alpha");
-            let v_function = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function");
+            let lambda = parse_arg::<f32>(subcommand_matches, "lambda");
+            if !(0. ..=1.).contains(&lambda) {
+                exit_with_error("lambda must be between 0 and 1");
+            }
+            let temporal_coherence = subcommand_matches.is_present("temporal-coherence");
+            let weights = subcommand_matches.value_of("weights");
+            let output = subcommand_matches.value_of("output");
             let format = parse_arg::<OutputFormat>(subcommand_matches, "format");
             let benchmark_interval = parse_arg::<u64>(subcommand_matches, "benchmark-interval");
+            let seed = parse_seed(subcommand_matches);
+            let resume = subcommand_matches.value_of("resume");
+
+            // A resumed run continues with the v-function of its checkpoint
+            let v_function = match resume {
+                Some(path) => read_v_function(path)
+                    .unwrap_or_else(|err| exit_with_error(err))
+                    .parse::<VFunctionChoice>()
+                    .unwrap_or_else(|_| exit_with_error("unknown v-function in the checkpoint")),
+                None => parse_arg::<VFunctionChoice>(subcommand_matches, "v_function"),
+            };
 
-            match v_function {
-                VFunctionChoice::Legacy => {
-                    train::<Legacy>(num_batches, alpha, zero, format, benchmark_interval)
-                }
-                VFunctionChoice::NTupleSmall => {
-                    train::<NTupleSmall>(num_batches, alpha, zero, format, benchmark_interval)
-                }
-                VFunctionChoice::NTupleMedium => {
-                    train::<NTupleMedium>(num_batches, alpha, zero, format, benchmark_interval)
-                }
+            let checkpoint_interval = parse_arg::<u64>(subcommand_matches, "checkpoint-interval");
+            if benchmark_interval == 0 {
+                exit_with_error("the benchmark interval must be at least 1");
+            }
+            if checkpoint_interval == 0 {
+                exit_with_error("the checkpoint interval must be at least 1");
+            }
+            let checkpoint = subcommand_matches
+                .value_of("checkpoint")
+                .or(resume)
+                .map(|path| CheckpointConfig {
+                    path: path.into(),
+                    interval: checkpoint_interval,
+                });
+
+            let options = TrainOptions {
+                config: TrainingConfig {
+                    num_games,
+                    alpha,
+                    lambda,
+                    temporal_coherence,
+                    benchmark_interval,
+                    seed,
+                    checkpoint,
+                },
+                engine_config: parse_engine_config(subcommand_matches),
+                zero,
+                weights,
+                resume,
+                output,
+                format,
             };
+
+            with_v_function!(v_function, train(options));
+        }
+        "compare" => {
+            let subcommand_matches = matches.subcommand_matches("compare").unwrap();
+            let config = parse_engine_config(subcommand_matches);
+            let format = parse_arg::<OutputFormat>(subcommand_matches, "format");
+            let max_games = parse_arg::<u64>(subcommand_matches, "N");
+            if max_games == 0 {
+                exit_with_error("the number of games must be at least 1");
+            }
+            let compare_config = CompareConfig {
+                max_games,
+                margin: parse_arg::<f32>(subcommand_matches, "margin"),
+                seed: parse_seed(subcommand_matches),
+            };
+
+            let v_function_a = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function_a");
+            let v_function_b = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function_b");
+            let a = Contestant {
+                weights: subcommand_matches.value_of("weights_a"),
+                limit: SearchLimit::Depth(parse_depth(subcommand_matches, "depth_a")),
+            };
+            let b = Contestant {
+                weights: subcommand_matches.value_of("weights_b"),
+                limit: SearchLimit::Depth(parse_depth(subcommand_matches, "depth_b")),
+            };
+
+            with_v_function!(
+                v_function_a,
+                v_function_b,
+                compare(a, b, config, &compare_config, format)
+            );
+        }
+        "analyze" => {
+            let subcommand_matches = matches.subcommand_matches("analyze").unwrap();
+            let board = parse_board(subcommand_matches.value_of("BOARD").unwrap())
+                .unwrap_or_else(|err| exit_with_error(err));
+            let depth = parse_arg::<u8>(subcommand_matches, "depth");
+            let config = parse_engine_config(subcommand_matches);
+            let v_function = parse_arg::<VFunctionChoice>(subcommand_matches, "v_function");
+            let weights = subcommand_matches.value_of("weights");
+            let format = parse_arg::<OutputFormat>(subcommand_matches, "format");
+            let color = subcommand_matches.is_present("color");
+
+            with_v_function!(
+                v_function,
+                analyze(weights, config, board, depth, format, color)
+            );
+        }
+        "replay" => {
+            let subcommand_matches = matches.subcommand_matches("replay").unwrap();
+            let path = subcommand_matches.value_of("FILE").unwrap();
+            let step = subcommand_matches.is_present("step");
+            let color = subcommand_matches.is_present("color");
+
+            replay(path, step, color);
+        }
+        "convert" => {
+            let subcommand_matches = matches.subcommand_matches("convert").unwrap();
+            let input = subcommand_matches.value_of("INPUT").unwrap();
+            let output = subcommand_matches.value_of("OUTPUT").unwrap();
+            let format = parse_arg::<WeightsFormatChoice>(subcommand_matches, "to");
+
+            let v_function = read_v_function(input)
+                .unwrap_or_else(|err| exit_with_error(err))
+                .parse::<VFunctionChoice>()
+                .unwrap_or_else(|_| exit_with_error("unknown v-function in the weights file"));
+
+            with_v_function!(v_function, convert(input, output, format));
         }
         _ => unreachable!(),
     }
 }
 
-fn play(engine: &mut Engine<impl VFunction>, depth: u8) {
-    let board = play_random_game(engine, depth, true);
-    println!("Final Score: {}", board.score());
+/// Reads the `--seed` argument, picking a random seed when it's absent
+fn parse_seed(matches: &ArgMatches) -> u64 {
+    if matches.is_present("seed") {
+        parse_arg::<u64>(matches, "seed")
+    } else {
+        random()
+    }
+}
+
+/// Parses a search depth, which must be at least 1
+fn parse_depth(matches: &ArgMatches, name: &str) -> u8 {
+    let depth = parse_arg::<u8>(matches, name);
+    if depth == 0 {
+        exit_with_error(format!("--{} must be at least 1", name));
+    }
+
+    depth
+}
+
+/// Reads the engine arguments, keeping the engine's defaults for those a command doesn't take
+fn parse_engine_config(matches: &ArgMatches) -> EngineConfig {
+    let mut config = EngineConfig {
+        threads: parse_arg::<usize>(matches, "threads"),
+        canonical_keys: matches.is_present("canonical-keys"),
+        ..EngineConfig::default()
+    };
+
+    if matches.is_present("table-size") {
+        config.table_size = parse_arg::<usize>(matches, "table-size");
+    }
+    if matches.is_present("replacement") {
+        config.replacement =
+            parse_arg::<ReplacementChoice>(matches, "replacement").into_replacement();
+    }
+    if matches.is_present("prob-cutoff") {
+        config.prob_cutoff = parse_arg::<f32>(matches, "prob-cutoff");
+    }
+
+    config
+}
+
+/// Reads the `--time-per-move` argument, falling back on `--depth` when it's absent, adapted to
+/// the board with `--min-depth`
+fn parse_search_limit(matches: &ArgMatches) -> SearchLimit {
+    if matches.is_present("time-per-move") {
+        SearchLimit::Time(Duration::from_millis(parse_arg::<u64>(
+            matches,
+            "time-per-move",
+        )))
+    } else if matches.is_present("min-depth") {
+        let policy = AdaptiveDepth {
+            min_depth: parse_arg::<u8>(matches, "min-depth"),
+            max_depth: parse_depth(matches, "depth"),
+        };
+        if policy.min_depth == 0 || policy.min_depth > policy.max_depth {
+            exit_with_error("the minimum depth must be between 1 and --depth");
+        }
+
+        SearchLimit::Adaptive(Arc::new(policy))
+    } else {
+        SearchLimit::Depth(parse_depth(matches, "depth"))
+    }
+}
+
+fn play<F: VFunction>(
+    weights: Option<&str>,
+    config: EngineConfig,
+    limit: SearchLimit,
+    seed: u64,
+    record: Option<&str>,
+    color: bool,
+) {
+    let mut engine = Engine::<F>::with_config(load_weights(weights), config);
+    let mut total_nodes = 0;
+    let mut total_seconds = 0.;
+    let print_board = |game: &Game, stats: Option<&SearchStats>| {
+        println!("{}", render_board(game.big_board(), color));
+        if let Some(stats) = stats {
+            println!("Search: {}", render_stats(stats));
+            println!();
+            total_nodes += stats.nodes();
+            total_seconds += stats.elapsed;
+        }
+    };
+
+    let game = match record {
+        Some(path) => {
+            let (game, recording) = play_recorded_game(&mut engine, limit, seed, print_board);
+            recording
+                .save(path)
+                .unwrap_or_else(|err| exit_with_error(err));
+            game
+        }
+        None => play_random_game(&mut engine, limit, seed, print_board),
+    };
+
+    println!("Final Score: {}", game.score());
+    println!("Seed: {}", seed);
+    println!(
+        "Searched {} nodes in {:.1}s ({:.0} nodes/s)",
+        total_nodes,
+        total_seconds,
+        total_nodes as f32 / total_seconds.max(f32::EPSILON)
+    );
+}
+
+fn analyze<F: VFunction>(
+    weights: Option<&str>,
+    config: EngineConfig,
+    board: Board,
+    depth: u8,
+    format: OutputFormat,
+    color: bool,
+) {
+    if depth == 0 {
+        exit_with_error("the depth must be at least 1");
+    }
+
+    let mut engine = Engine::<F>::with_config(load_weights(weights), config);
+    let analysis = engine.analyze(board, depth);
+
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string(&analysis).unwrap());
+        return;
+    }
+
+    println!("{}", render_board(board.into(), color));
+
+    if analysis.moves.is_empty() {
+        println!("The game is over.");
+        return;
+    }
+
+    println!("{:<8}{:>12}{:>14}", "Move", "Value", "Static eval");
+    for mov in &analysis.moves {
+        println!(
+            "{:<8}{:>12.0}{:>14.0}",
+            format!("{:?}", mov.direction),
+            mov.value,
+            mov.static_eval
+        );
+    }
+
+    println!();
+    println!("Search: {}", render_stats(&analysis.stats));
+    println!(
+        "Transposition table: {:.1}% hits ({} hits, {} misses, {} collisions), {:.1}% full",
+        analysis.table.hit_rate() * 100.,
+        analysis.table.hits,
+        analysis.table.misses,
+        analysis.table.collisions,
+        analysis.table.fill_rate * 100.
+    );
 }
 
-fn bench(engine: &mut Engine<impl VFunction>, num_games: u64, depth: u8) {
+fn replay(path: &str, step: bool, color: bool) {
+    let recording = Recording::load(path).unwrap_or_else(|err| exit_with_error(err));
+    let boards = recording.boards();
+
+    if let Some(seed) = recording.seed {
+        println!("Seed: {}", seed);
+    }
+    println!("{}", render_board(boards[0], color));
+
+    for (i, (mov, board)) in recording.moves.iter().zip(&boards[1..]).enumerate() {
+        if step {
+            let mut line = String::new();
+            io::stdin()
+                .read_line(&mut line)
+                .unwrap_or_else(|err| exit_with_error(err));
+        }
+
+        print!("Move {}: {:?}", i + 1, mov.direction);
+        if let Some(eval) = mov.eval {
+            print!(" (eval {:.0})", eval);
+        }
+        println!(
+            ", a {} spawned at ({}, {})",
+            1 << mov.spawn.tile.exponent(),
+            mov.spawn.x,
+            mov.spawn.y
+        );
+        println!("{}", render_board(*board, color));
+    }
+
+    println!("Final Score: {}", recording.score());
+}
+
+fn bench<F: VFunction>(
+    weights: Option<&str>,
+    config: EngineConfig,
+    num_games: u64,
+    limit: SearchLimit,
+    seed: u64,
+    format: OutputFormat,
+) {
+    let engine = Engine::<F>::with_config(load_weights(weights), config);
+
+    // The progress bar is drawn on stderr, so it doesn't mix with machine-readable output
     let play_games_bar = ProgressBar::new(num_games);
     play_games_bar.set_message("Playing games");
     play_games_bar.set_style(ProgressStyle::default_bar().template("{msg} {wide_bar} {eta}"));
     play_games_bar.tick();
 
-    let results = benchmark(engine, num_games, depth, |_| play_games_bar.inc(1));
+    let results = benchmark(&engine, num_games, limit, seed, |_| play_games_bar.inc(1));
 
     play_games_bar.finish();
-    println!();
 
-    println!("{} games played.", num_games);
+    match format {
+        OutputFormat::Human => {}
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string(&results).unwrap());
+            return;
+        }
+        OutputFormat::Csv => {
+            println!("seed,score,max_tile,moves,four_spawns,seconds");
+            for game in &results.games {
+                println!(
+                    "{},{},{},{},{},{}",
+                    game.seed,
+                    game.score,
+                    game.max_tile,
+                    game.moves,
+                    game.four_spawns,
+                    game.seconds
+                );
+            }
+            return;
+        }
+    }
+
+    println!();
+    println!("{} games played (seed {}).", num_games, seed);
     println!(
         "Average score: {:.0} \u{00b1} {:.0}",
         results.average, results.error
@@ -200,47 +805,159 @@ fn bench(engine: &mut Engine<impl VFunction>, num_games: u64, depth: u8) {
         "Confidence interval (95%): [{:.0}, {:.0}]",
         results.lower_bound, results.upper_bound
     );
+    println!(
+        "Median score: {:.0} (min {:.0}, max {:.0})",
+        results.median, results.min, results.max
+    );
+    println!(
+        "Percentiles: p10 {:.0}, p25 {:.0}, p75 {:.0}, p90 {:.0}",
+        results.p10, results.p25, results.p75, results.p90
+    );
     println!();
 
-    for n in 8..13 {
+    // The tiles above 4096 are only listed once a game reaches them
+    for n in (8..=MAX_EXPONENT).filter(|&n| n < 13 || results.tiles_reached[n as usize] > 0.) {
         println!(
             "{}: {}%",
-            u64::pow(2, n),
+            1u64 << n,
             results.tiles_reached[n as usize] * 100.
         );
     }
 }
 
-fn train<F>(num_batches: u64, alpha: f32, zero: bool, format: OutputFormat, benchmark_interval: u64)
-where
-    F: VFunction,
-{
-    let weights = if zero {
-        F::Weights::default()
-    } else {
-        F::Weights::optimized()
-    };
+/// One of the two engines of a comparison
+struct Contestant<'a> {
+    weights: Option<&'a str>,
+    limit: SearchLimit,
+}
+
+fn compare<A: VFunction, B: VFunction>(
+    a: Contestant,
+    b: Contestant,
+    config: EngineConfig,
+    compare_config: &CompareConfig,
+    format: OutputFormat,
+) {
+    let engine_a = Engine::<A>::with_config(load_weights(a.weights), config.clone());
+    let engine_b = Engine::<B>::with_config(load_weights(b.weights), config);
+
+    let play_games_bar = ProgressBar::new(compare_config.max_games);
+    play_games_bar.set_message("Playing games");
+    play_games_bar.set_style(ProgressStyle::default_bar().template("{msg} {wide_bar} {eta}"));
+    play_games_bar.tick();
 
-    let mut engine = Engine::<F>::new(weights);
-
-    train_td(
-        &mut engine,
-        num_batches,
-        alpha,
-        benchmark_interval,
-        |progress| match format {
-            OutputFormat::Human => println!(
-                "Game {}, Average Score: {}",
-                progress.game, progress.test_score
-            ),
-            OutputFormat::Json => println!("{}", serde_json::to_string(&progress).unwrap()),
-        },
+    let result = testing::compare(
+        &engine_a,
+        a.limit,
+        &engine_b,
+        b.limit,
+        compare_config,
+        |games| play_games_bar.set_position(games),
     );
 
+    play_games_bar.finish_and_clear();
+
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string(&result).unwrap());
+        return;
+    }
+
+    println!(
+        "{} games played by each engine (seed {}).",
+        result.games, compare_config.seed
+    );
+    println!("Average score of A: {:.0}", result.average_a);
+    println!("Average score of B: {:.0}", result.average_b);
+    println!(
+        "Difference (A - B): {:.0} \u{00b1} {:.0}",
+        result.difference, result.error
+    );
+    println!(
+        "Confidence interval (95%): [{:.0}, {:.0}]",
+        result.lower_bound, result.upper_bound
+    );
+    println!("Log-likelihood ratio: {:.2}", result.llr);
+
+    match result.verdict {
+        Verdict::AStronger => println!("A is stronger."),
+        Verdict::BStronger => println!("B is stronger."),
+        Verdict::Inconclusive => println!("Inconclusive."),
+    }
+}
+
+struct TrainOptions<'a> {
+    config: TrainingConfig,
+    engine_config: EngineConfig,
+    zero: bool,
+    weights: Option<&'a str>,
+    resume: Option<&'a str>,
+    output: Option<&'a str>,
+    format: OutputFormat,
+}
+
+fn train<F: VFunction>(options: TrainOptions) {
+    let (weights, config, training) = match options.resume {
+        Some(path) => {
+            let checkpoint =
+                Checkpoint::<F::Weights>::load(path).unwrap_or_else(|err| exit_with_error(err));
+            let config = TrainingConfig {
+                num_games: options.config.num_games,
+                checkpoint: options.config.checkpoint,
+                ..checkpoint.config
+            };
+
+            (checkpoint.weights, config, checkpoint.state)
+        }
+        None => {
+            let weights = if options.zero {
+                F::Weights::default()
+            } else {
+                load_weights(options.weights)
+            };
+            let training = TrainingState::new(options.config.seed);
+
+            (weights, options.config, training)
+        }
+    };
+
+    let mut engine = Engine::<F>::with_config(weights, options.engine_config);
+    let format = options.format;
+
+    resume_td(&mut engine, &config, training, |progress| match format {
+        OutputFormat::Human => println!(
+            "Game {}, Average Score: {}",
+            progress.game, progress.test_score
+        ),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&progress).unwrap()),
+        OutputFormat::Csv => unreachable!("train doesn't accept the csv format"),
+    })
+    .unwrap_or_else(|err| exit_with_error(err));
+
     let new_weights = engine.into_weights();
 
+    if let Some(path) = options.output {
+        new_weights
+            .save(path)
+            .unwrap_or_else(|err| exit_with_error(err));
+        return;
+    }
+
     match format {
         OutputFormat::Human => println!("{:?}", new_weights),
-        OutputFormat::Json => println!("{}", serde_json::to_string(&new_weights).unwrap()),
+        OutputFormat::Json => {
+            new_weights
+                .write_to(io::stdout())
+                .unwrap_or_else(|err| exit_with_error(err));
+            println!();
+        }
+        OutputFormat::Csv => unreachable!("train doesn't accept the csv format"),
     };
 }
+
+fn convert<F: VFunction>(input: &str, output: &str, format: WeightsFormatChoice) {
+    let weights: F::Weights = load_weights(Some(input));
+
+    weights
+        .save_as(output, format.into_weights_format())
+        .unwrap_or_else(|err| exit_with_error(err));
+}
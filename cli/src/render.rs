@@ -0,0 +1,61 @@
+use swipy_engine::{BigBoard, SearchStats, MAX_EXPONENT};
+
+/// The 256-color palette backgrounds of the tiles, indexed by exponent
+const TILE_COLORS: [u8; MAX_EXPONENT as usize + 1] = [
+    236, 255, 230, 215, 209, 203, 196, 228, 227, 226, 220, 214, 99, 63, 27, 21, 19, 17,
+];
+
+/// Draws the board as an aligned grid for the terminal, with every tile colored according to its
+/// value when `color` is set
+pub fn render_board(board: BigBoard, color: bool) -> String {
+    let mut text = String::new();
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let exponent = board.at(x, y);
+            let cell = match exponent {
+                0 => format!("{:^7}", "."),
+                _ => format!("{:^7}", 1u64 << exponent),
+            };
+
+            if color {
+                // Dark text on the light tiles, light text on the others
+                let foreground = if exponent <= 2 { 235 } else { 255 };
+                text.push_str(&format!(
+                    "\x1b[38;5;{}m\x1b[48;5;{}m{}\x1b[0m",
+                    foreground, TILE_COLORS[exponent as usize], cell
+                ));
+            } else {
+                text.push_str(&cell);
+            }
+        }
+
+        text.push('\n');
+    }
+
+    text
+}
+
+/// Summarizes a search on one line, with the nodes visited from the root's depth down to the
+/// static evaluations
+pub fn render_stats(stats: &SearchStats) -> String {
+    let per_depth: Vec<String> = stats
+        .nodes_per_depth
+        .iter()
+        .rev()
+        .map(|nodes| nodes.to_string())
+        .collect();
+
+    format!(
+        "depth {}: {} nodes ({}), {} chance nodes, {} cutoffs, {} table hits, \
+         branching factor {:.1}, {:.3}s",
+        stats.depth,
+        stats.nodes(),
+        per_depth.join(" / "),
+        stats.chance_nodes,
+        stats.cutoffs,
+        stats.table_hits,
+        stats.branching_factor(),
+        stats.elapsed
+    )
+}
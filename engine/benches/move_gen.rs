@@ -2,32 +2,90 @@
 extern crate criterion;
 extern crate swipy_engine;
 
-use criterion::Criterion;
-use swipy_engine::Board;
+use criterion::{Criterion, Fun};
+use std::cell::RefCell;
+use swipy_engine::v_function::{Legacy, LegacyWeights, Weights};
+use swipy_engine::{Board, Engine};
 
-fn gen_moves(c: &mut Criterion) {
-    let empty_board = Board::from_u64(0x0000_0100_0000_0000);
-    c.bench_function("gen_moves (empty board)", move |b| {
-        b.iter(|| empty_board.gen_moves())
-    });
+const EMPTY_BOARD: u64 = 0x0000_0100_0000_0000;
+const FULL_BOARD: u64 = 0xBA92_7621_0221_1001;
 
-    let full_board = Board::from_u64(0xBA92_7621_0221_1001);
-    c.bench_function("gen_moves (full board)", move |b| {
-        b.iter(|| full_board.gen_moves())
-    });
+/// Compares collecting the moves in a `Vec` with iterating over them. Both visit every
+/// afterstate, like a search does.
+fn moves(c: &mut Criterion) {
+    for &(name, board) in &[("empty board", EMPTY_BOARD), ("full board", FULL_BOARD)] {
+        let gen_moves = Fun::new("gen_moves", |b, &board: &u64| {
+            let board = Board::from_u64(board);
+            b.iter(|| {
+                board
+                    .gen_moves()
+                    .into_iter()
+                    .fold(0, |acc, (_, afterstate)| acc ^ afterstate.into_u64())
+            })
+        });
+        let moves = Fun::new("moves", |b, &board: &u64| {
+            let board = Board::from_u64(board);
+            b.iter(|| {
+                board
+                    .moves()
+                    .fold(0, |acc, (_, afterstate)| acc ^ afterstate.into_u64())
+            })
+        });
+
+        c.bench_functions(&format!("moves ({})", name), vec![gen_moves, moves], board);
+    }
+}
+
+/// Compares collecting the tile spawns in a `Vec` with iterating over them
+fn tile_spawns(c: &mut Criterion) {
+    for &(name, board) in &[("empty board", EMPTY_BOARD), ("full board", FULL_BOARD)] {
+        let gen_tile_spawns = Fun::new("gen_tile_spawns", |b, &board: &u64| {
+            let board = Board::from_u64(board);
+            b.iter(|| {
+                board
+                    .gen_tile_spawns()
+                    .into_iter()
+                    .fold(0, |acc, (_, _, spawned)| acc ^ spawned.into_u64())
+            })
+        });
+        let tile_spawns = Fun::new("tile_spawns", |b, &board: &u64| {
+            let board = Board::from_u64(board);
+            b.iter(|| {
+                board
+                    .tile_spawns()
+                    .fold(0, |acc, (_, _, spawned)| acc ^ spawned.into_u64())
+            })
+        });
+
+        c.bench_functions(
+            &format!("tile_spawns ({})", name),
+            vec![gen_tile_spawns, tile_spawns],
+            board,
+        );
+    }
 }
 
-fn gen_tile_spawns(c: &mut Criterion) {
-    let empty_board = Board::from_u64(0x0000_0100_0000_0000);
-    c.bench_function("gen_tile_spawns (empty board)", move |b| {
-        b.iter(|| empty_board.gen_tile_spawns())
+/// Searches from scratch, so that the transposition table doesn't hide the cost of the nodes.
+/// Clearing the table isn't timed.
+fn search(c: &mut Criterion) {
+    let engine = RefCell::new(Engine::<Legacy>::new(LegacyWeights::optimized().unwrap()));
+    let early_board = Board::from_u64(0x0000_0100_0000_0001);
+    c.bench_function("search depth 3 (early game)", move |b| {
+        b.iter_with_setup(
+            || engine.borrow_mut().reset(),
+            |()| engine.borrow_mut().search(early_board, 3),
+        )
     });
 
-    let full_board = Board::from_u64(0xBA92_7621_0221_1001);
-    c.bench_function("gen_tile_spawns (full board)", move |b| {
-        b.iter(|| full_board.gen_tile_spawns())
+    let engine = RefCell::new(Engine::<Legacy>::new(LegacyWeights::optimized().unwrap()));
+    let mid_board = Board::from_u64(0x0001_0012_1235_2367);
+    c.bench_function("search depth 3 (mid game)", move |b| {
+        b.iter_with_setup(
+            || engine.borrow_mut().reset(),
+            |()| engine.borrow_mut().search(mid_board, 3),
+        )
     });
 }
 
-criterion_group!(benches, gen_moves, gen_tile_spawns);
+criterion_group!(benches, moves, tile_spawns, search);
 criterion_main!(benches);
@@ -0,0 +1,56 @@
+use crate::game::Board;
+use std::fmt::Debug;
+
+/// Chooses how deep the engine searches each move, see `SearchLimit::Adaptive`
+pub trait DepthPolicy: Debug + Send + Sync {
+    /// The depth of the search for the best move of `board`, which must be at least 1
+    fn depth(&self, board: Board) -> u8;
+}
+
+/// Searches shallowly on open boards, where most moves are fine, and deeply on crowded boards,
+/// where a single mistake can end the game.
+///
+/// The depth starts at `min_depth` and goes up by one for each of: at most 6 empty cells, at
+/// most 3 empty cells, at least 9 distinct tiles, and a 2048 tile or higher. It never goes past
+/// `max_depth`. Both depths must be at least 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveDepth {
+    pub min_depth: u8,
+    pub max_depth: u8,
+}
+
+impl DepthPolicy for AdaptiveDepth {
+    fn depth(&self, board: Board) -> u8 {
+        let empties = board.count_empties();
+        let difficulties = [
+            empties <= 6,
+            empties <= 3,
+            board.count_distinct_tiles() >= 9,
+            board.highest_tile() >= 11,
+        ];
+        let extra_depth = difficulties.iter().filter(|&&hard| hard).count() as u8;
+
+        (self.min_depth + extra_depth).min(self.max_depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: AdaptiveDepth = AdaptiveDepth {
+        min_depth: 1,
+        max_depth: 4,
+    };
+
+    #[test]
+    fn deeper_on_full_boards() {
+        let open = Board::from_array([[1, 2, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+        let crowded = Board::from_array([[1, 2, 3, 4], [5, 6, 7, 8], [9, 1, 2, 0], [0, 0, 0, 1]]);
+        let full = Board::from_array([[1, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 1], [2, 0, 0, 1]]);
+
+        assert_eq!(POLICY.depth(open), 1);
+        assert_eq!(POLICY.depth(crowded), 3);
+        assert_eq!(POLICY.depth(full), 4);
+    }
+}
@@ -1,18 +1,121 @@
+use crate::depth_policy::DepthPolicy;
 use crate::game::{Board, Direction, TileSpawn};
-use crate::transposition_table::{PositionEval, TranspositionTable};
-use crate::v_function::VFunction;
+use crate::search_stats::{NodeCounters, SearchStats};
+use crate::transposition_table::{PositionEval, Replacement, TableStats, TranspositionTable};
+use crate::v_function::{Coherence, VFunction};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde_derive::Serialize;
 use std::iter::Iterator;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// The search depth counter increase when processing a move where a 4 spawns.
 /// This is approximately equal to ln(0.1) / ln(0.9) = 21.85434532678.
 const DEPTH_PENALTY_4: u8 = 22;
 
+/// The deepest iteration attempted by `Engine::search_timed`, which bounds the search on boards
+/// where every iteration is nearly free, such as boards close to game over.
+const MAX_ITERATIVE_DEPTH: u8 = 16;
+
+/// The shallowest chance node whose children are searched in parallel. Below this depth, the
+/// subtrees are too small to be worth sending to another thread.
+const PARALLEL_MIN_DEPTH: u8 = 2;
+
+/// Bounds the amount of work done by the engine to pick a move
+#[derive(Debug, Clone)]
+pub enum SearchLimit {
+    /// Searches to a fixed depth, which must be at least 1
+    Depth(u8),
+    /// Deepens the search iteratively until the time budget is spent
+    Time(Duration),
+    /// Searches to the depth chosen by the policy for each board
+    Adaptive(Arc<dyn DepthPolicy>),
+}
+
+/// The tunable parameters of the engine
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// The number of threads used by a search
+    pub threads: usize,
+    /// Searches the positions in their `Board::canonical` form, so that the rotations and
+    /// reflections of a position share their evaluation in the transposition table. The
+    /// evaluations are only exact for v-functions that are themselves symmetric.
+    pub canonical_keys: bool,
+    /// The memory taken by the transposition table, in megabytes. Resetting the engine clears
+    /// the whole table, so engines reset for every short game, such as those of a benchmark,
+    /// spend less time clearing a small table.
+    pub table_size: usize,
+    pub replacement: Replacement,
+    /// The chance nodes reached with a probability below this threshold are evaluated statically
+    /// instead of being searched. The probability of a node is the product of the probabilities
+    /// of the spawns leading to it from the root, and a threshold of 0 searches every node.
+    pub prob_cutoff: f32,
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            threads: 1,
+            canonical_keys: false,
+            table_size: 4,
+            replacement: Replacement::TwoTier,
+            prob_cutoff: 0.,
+        }
+    }
+}
+
+/// The evaluation of one of the legal moves of an analyzed position
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveAnalysis {
+    pub direction: Direction,
+    /// The expectimax value of the move's afterstate, which the search maximizes
+    pub value: f32,
+    /// The v-function's evaluation of the move's afterstate
+    pub static_eval: f32,
+}
+
+/// The result of `Engine::analyze`
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    pub depth: u8,
+    /// Every legal move, from the best to the worst
+    pub moves: Vec<MoveAnalysis>,
+    /// What the search did
+    pub stats: SearchStats,
+    /// What the transposition table did during the search
+    pub table: TableStats,
+}
+
+/// The expectimax value of a node
+#[derive(Debug, Clone, Copy)]
+struct NodeValue {
+    score: f32,
+    /// Whether `prob_cutoff` evaluated part of the subtree statically
+    pruned: bool,
+}
+
+impl NodeValue {
+    fn exact(score: f32) -> NodeValue {
+        NodeValue {
+            score,
+            pruned: false,
+        }
+    }
+}
+
 pub struct Engine<F>
 where
     F: VFunction,
 {
     v_function: F,
     transposition_table: TranspositionTable,
+    thread_pool: Option<ThreadPool>,
+    config: EngineConfig,
+    deadline: Option<Instant>,
+    timed_out: AtomicBool,
+    counters: NodeCounters,
+    last_stats: SearchStats,
 }
 
 impl<F> Engine<F>
@@ -20,82 +123,342 @@ where
     F: VFunction,
 {
     pub fn new(weights: F::Weights) -> Self {
-        let transposition_table = TranspositionTable::new(0x1000);
+        Engine::with_config(weights, EngineConfig::default())
+    }
+
+    pub fn with_config(weights: F::Weights, config: EngineConfig) -> Self {
+        let transposition_table = TranspositionTable::new(config.table_size, config.replacement);
+
+        let thread_pool = if config.threads > 1 {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(config.threads)
+                .build()
+                .expect("the thread pool can be created");
+            Some(pool)
+        } else {
+            None
+        };
 
         Engine {
             v_function: F::new(weights),
             transposition_table,
+            thread_pool,
+            config,
+            deadline: None,
+            timed_out: AtomicBool::new(false),
+            counters: NodeCounters::new(0, 1),
+            last_stats: SearchStats::default(),
         }
     }
 
     /// Recursively searches for the best move to perform with the current game state
     /// Uses afterstates as leaves to statically evaluate
+    ///
+    /// When the engine has several threads, the result is the same from one run to the next.
+    ///
+    /// Panics if `depth` is 0.
     pub fn search(&mut self, board: Board, depth: u8) -> Direction {
-        let moves = board.gen_moves();
+        self.search_depth(board, depth).0
+    }
 
-        moves
-            .into_iter()
-            .map(|(dir, board)| (dir, self.expectimax_spawn_tile(board, depth - 1)))
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("values are not NAN"))
-            .expect("moves has elements")
-            .0
+    /// Searches with iterative deepening until `budget` has elapsed.
+    ///
+    /// Returns the best move found by the deepest iteration that finished in time. The
+    /// transposition table is kept between iterations, so deeper iterations reuse the results of
+    /// the shallower ones.
+    pub fn search_timed(&mut self, board: Board, budget: Duration) -> Direction {
+        self.search_deepening(board, budget).0
     }
 
-    /// Evaluates the expected score of a position using expectimax.
+    /// Searches for the best move within the given limit
+    pub fn search_with(&mut self, board: Board, limit: SearchLimit) -> Direction {
+        self.search_with_eval(board, limit).0
+    }
+
+    /// Like `search_with`, but also returns the expected score of the afterstate of the best move
+    pub fn search_with_eval(&mut self, board: Board, limit: SearchLimit) -> (Direction, f32) {
+        match limit {
+            SearchLimit::Depth(depth) => self.search_depth(board, depth),
+            SearchLimit::Time(budget) => self.search_deepening(board, budget),
+            SearchLimit::Adaptive(policy) => self.search_depth(board, policy.depth(board)),
+        }
+    }
+
+    /// Searches every legal move of `board` to `depth` and reports all their values, instead of
+    /// only the best move. The moves are empty when the game is over.
     ///
-    /// The `board` argument represents a state of the board between turns.
-    fn expectimax_move(&mut self, board: Board, depth: u8) -> f32 {
+    /// Panics if `depth` is 0.
+    pub fn analyze(&mut self, board: Board, depth: u8) -> Analysis {
+        assert!(depth >= 1, "the search depth must be at least 1");
+        let moves = board.gen_moves();
+        self.start_stats(depth);
+
+        let values = match self.thread_pool {
+            Some(ref pool) => pool.install(|| self.eval_root_moves(&moves, depth)),
+            None => self.eval_root_moves(&moves, depth),
+        };
+
+        let mut moves: Vec<MoveAnalysis> = moves
+            .iter()
+            .zip(values)
+            .map(|(&(direction, afterstate), value)| MoveAnalysis {
+                direction,
+                value,
+                static_eval: self.static_eval(afterstate),
+            })
+            .collect();
+        moves.sort_by(|a, b| b.value.partial_cmp(&a.value).expect("values are not NAN"));
+
+        self.finish_stats(depth);
+
+        Analysis {
+            depth,
+            moves,
+            stats: self.last_stats.clone(),
+            table: self.transposition_table.stats(),
+        }
+    }
+
+    fn search_depth(&mut self, board: Board, depth: u8) -> (Direction, f32) {
+        assert!(depth >= 1, "the search depth must be at least 1");
         let moves = board.gen_moves();
-        if moves.is_empty() {
-            return 0.;
+        self.start_stats(depth);
+
+        let best = self
+            .run_search(&moves, depth)
+            .expect("search without a deadline always finishes");
+
+        self.finish_stats(depth);
+        best
+    }
+
+    fn search_deepening(&mut self, board: Board, budget: Duration) -> (Direction, f32) {
+        let moves = board.gen_moves();
+        let &(only_move, afterstate) = moves.first().expect("moves has elements");
+        let mut best = (only_move, self.static_eval(afterstate));
+        self.start_stats(MAX_ITERATIVE_DEPTH);
+
+        if moves.len() == 1 {
+            self.finish_stats(0);
+            return best;
         }
 
-        moves
+        self.deadline = Some(Instant::now() + budget);
+        self.timed_out.store(false, Ordering::Relaxed);
+
+        let mut finished_depth = 0;
+        for depth in 1..=MAX_ITERATIVE_DEPTH {
+            match self.run_search(&moves, depth) {
+                Some(result) => best = result,
+                None => break,
+            }
+            finished_depth = depth;
+        }
+
+        self.deadline = None;
+        self.timed_out.store(false, Ordering::Relaxed);
+        self.finish_stats(finished_depth);
+
+        best
+    }
+
+    /// Resets the node counters and the table statistics before a search
+    fn start_stats(&mut self, max_depth: u8) {
+        self.counters = NodeCounters::new(max_depth, self.threads());
+        self.transposition_table.reset_stats();
+    }
+
+    /// Saves the statistics of the search that just finished at `depth`
+    fn finish_stats(&mut self, depth: u8) {
+        let table_hits = self.transposition_table.hits();
+        self.last_stats = self.counters.stats(depth, table_hits);
+    }
+
+    /// Runs `search_root` on the engine's thread pool, if it has one
+    fn run_search(&self, moves: &[(Direction, Board)], depth: u8) -> Option<(Direction, f32)> {
+        match self.thread_pool {
+            Some(ref pool) => pool.install(|| self.search_root(moves, depth)),
+            None => self.search_root(moves, depth),
+        }
+    }
+
+    /// Picks the best of the root moves along with its value, or returns `None` if the deadline
+    /// was reached before the search finished.
+    fn search_root(&self, moves: &[(Direction, Board)], depth: u8) -> Option<(Direction, f32)> {
+        let scores = self.eval_root_moves(moves, depth);
+
+        let (&(best_move, _), best_score) = moves
             .iter()
+            .zip(scores)
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("values are not NAN"))
+            .expect("moves has elements");
+
+        if self.timed_out.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some((best_move, best_score))
+        }
+    }
+
+    /// Evaluates the afterstate of every root move
+    fn eval_root_moves(&self, moves: &[(Direction, Board)], depth: u8) -> Vec<f32> {
+        let eval_move = |(_, board): &(Direction, Board)| {
+            self.expectimax_spawn_tile(*board, depth - 1, 1.).score
+        };
+
+        if self.is_parallel() {
+            moves.par_iter().map(eval_move).collect()
+        } else {
+            moves.iter().map(eval_move).collect()
+        }
+    }
+
+    /// Evaluates the expected score of a position using expectimax.
+    ///
+    /// The `board` argument represents a state of the board between turns, which is reached with
+    /// probability `prob`.
+    fn expectimax_move(&self, board: Board, depth: u8, prob: f32) -> NodeValue {
+        self.counters.count_move_node(depth);
+
+        board
+            .moves()
             .map(|(_, next_board)| {
-                self.expectimax_spawn_tile(*next_board, depth)
-                    + (next_board.score() - board.score())
+                let value = self.expectimax_spawn_tile(next_board, depth, prob);
+                NodeValue {
+                    score: value.score + (next_board.score() - board.score()),
+                    pruned: value.pruned,
+                }
+            })
+            .fold(None, |best: Option<NodeValue>, value| match best {
+                // The last of the best moves wins, like with `max_by`
+                Some(best) => Some(NodeValue {
+                    score: if best.score > value.score {
+                        best.score
+                    } else {
+                        value.score
+                    },
+                    pruned: best.pruned || value.pruned,
+                }),
+                None => Some(value),
             })
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap()
+            .unwrap_or(NodeValue::exact(0.))
     }
 
     /// Evaluates the expected score of an afterstate using expectimax.
     ///
     /// The `board` argument represents an afterstate of the board, which is the state a board
-    /// takes after a move has been made, but before a random tile has appeared.
-    fn expectimax_spawn_tile(&mut self, board: Board, depth: u8) -> f32 {
+    /// takes after a move has been made, but before a random tile has appeared. It is reached with
+    /// probability `prob`.
+    fn expectimax_spawn_tile(&self, board: Board, depth: u8, prob: f32) -> NodeValue {
+        self.counters.count_chance_node(depth);
+
+        if depth >= 1 && self.is_out_of_time() {
+            return NodeValue::exact(0.);
+        }
+
+        // The symmetric boards of a canonical key sum their spawns in different orders, so they
+        // search the canonical board itself to agree on its value to the last bit
+        let board = if self.config.canonical_keys {
+            board.canonical()
+        } else {
+            board
+        };
+
         if depth >= 2 {
             match self.transposition_table.get(board) {
-                Some(eval) if eval.depth >= depth => {
-                    return eval.score;
+                Some(eval) if self.is_reusable(eval, depth, prob) => {
+                    return NodeValue::exact(eval.score);
                 }
                 _ => (),
             }
         }
 
         if depth == 0 {
-            return self.static_eval(board);
+            return NodeValue::exact(self.static_eval(board));
         }
 
-        let moves = board.gen_tile_spawns();
+        if prob < self.config.prob_cutoff {
+            self.counters.count_cutoff();
+            return NodeValue {
+                score: self.static_eval(board),
+                pruned: true,
+            };
+        }
 
-        let score = moves
-            .into_iter()
-            .map(|(prob, tile, board)| {
-                let new_depth = match tile {
-                    TileSpawn::Two => depth - 1,
-                    TileSpawn::Four => depth.saturating_sub(DEPTH_PENALTY_4),
-                };
+        let eval_spawn = |(spawn_prob, tile, board): (f32, TileSpawn, Board)| {
+            let new_depth = match tile {
+                TileSpawn::Two => depth - 1,
+                TileSpawn::Four => depth.saturating_sub(DEPTH_PENALTY_4),
+            };
 
-                prob * self.expectimax_move(board, new_depth)
-            })
-            .sum();
+            let value = self.expectimax_move(board, new_depth, prob * spawn_prob);
+            NodeValue {
+                score: spawn_prob * value.score,
+                pruned: value.pruned,
+            }
+        };
+        let add = |a: NodeValue, b: NodeValue| NodeValue {
+            score: a.score + b.score,
+            pruned: a.pruned || b.pruned,
+        };
 
-        self.transposition_table
-            .set(board, PositionEval::new(depth, score));
+        // The parallel scores are summed in order so that the result doesn't depend on scheduling.
+        // Only these nodes, which are close to the root, collect their spawns in a `Vec`.
+        let value = if self.is_parallel() && depth >= PARALLEL_MIN_DEPTH {
+            let spawns = board.gen_tile_spawns();
+            let values: Vec<NodeValue> = spawns.into_par_iter().map(eval_spawn).collect();
+            values.into_iter().fold(NodeValue::exact(0.), add)
+        } else {
+            board
+                .tile_spawns()
+                .map(eval_spawn)
+                .fold(NodeValue::exact(0.), add)
+        };
 
-        score
+        // The score of an interrupted search is meaningless and must not be reused. A pruned
+        // score depends on the probability of the path that reached the board, so threads racing
+        // to store it along different paths would make a parallel search nondeterministic.
+        let reusable = !value.pruned || !self.is_parallel();
+        if reusable && !self.timed_out.load(Ordering::Relaxed) {
+            self.transposition_table
+                .set(board, PositionEval::new(depth, value.score, prob));
+        }
+
+        value
+    }
+
+    /// Whether a stored evaluation can stand for the search of a board at `depth`, reached with
+    /// probability `prob`.
+    ///
+    /// Threads race to fill the table, so a parallel search only reuses the evaluations that
+    /// searching the board again would reproduce exactly: evaluations made at the same depth,
+    /// along a path no more likely than this one, so that `prob_cutoff` wouldn't prune more now
+    /// than it did then.
+    fn is_reusable(&self, eval: PositionEval, depth: u8, prob: f32) -> bool {
+        if self.is_parallel() {
+            eval.depth == depth && (self.config.prob_cutoff == 0. || prob >= eval.prob)
+        } else {
+            eval.depth >= depth
+        }
+    }
+
+    /// Checks whether the search deadline, if any, has passed
+    fn is_out_of_time(&self) -> bool {
+        if self.timed_out.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                self.timed_out.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn is_parallel(&self) -> bool {
+        self.thread_pool.is_some()
     }
 
     /// Statically evaluates the given position by evaluating the expected score
@@ -107,13 +470,238 @@ where
         self.v_function.learn(position, delta)
     }
 
+    /// Learns with the per-weight learning rates of temporal coherence
+    pub fn learn_coherent(&mut self, position: Board, delta: f32, coherence: &mut Coherence) {
+        self.v_function.learn_coherent(position, delta, coherence)
+    }
+
+    /// A copy of the current weights of the v-function
+    pub fn weights(&self) -> F::Weights {
+        self.v_function.clone().into_weights()
+    }
+
     /// Destroys the engine and returns the config
     pub fn into_weights(self) -> F::Weights {
         self.v_function.into_weights()
     }
 
-    /// Resets the state of the engine as if it was new
+    /// The statistics of the transposition table during the last search
+    pub fn table_stats(&self) -> TableStats {
+        self.transposition_table.stats()
+    }
+
+    /// What the last search or analysis did
+    pub fn search_stats(&self) -> &SearchStats {
+        &self.last_stats
+    }
+
+    /// Resets the state of the engine as if it was new. This clears the transposition table,
+    /// which takes time proportional to `EngineConfig::table_size`.
     pub fn reset(&mut self) {
         self.transposition_table.clear();
     }
+
+    /// The number of threads used by the engine
+    pub fn threads(&self) -> usize {
+        self.thread_pool
+            .as_ref()
+            .map_or(1, |pool| pool.current_num_threads())
+    }
+
+    /// Calls `op` once on each of the engine's threads, handing every thread its own
+    /// single-threaded copy of the engine. Useful to play independent games in parallel.
+    pub fn for_each_worker<OP>(&self, op: OP)
+    where
+        OP: Fn(&mut Engine<F>) + Sync,
+    {
+        match self.thread_pool {
+            Some(ref pool) => pool.scope(|scope| {
+                for _ in 0..pool.current_num_threads() {
+                    scope.spawn(|_| op(&mut self.worker()));
+                }
+            }),
+            None => op(&mut self.worker()),
+        }
+    }
+
+    /// Calls `op` once on each of the engine's threads, all of them sharing the engine. Useful to
+    /// play independent games in parallel with the same weights, without searching.
+    pub(crate) fn for_each_thread<OP>(&self, op: OP)
+    where
+        OP: Fn(&Engine<F>) + Sync,
+    {
+        match self.thread_pool {
+            Some(ref pool) => pool.scope(|scope| {
+                for _ in 0..pool.current_num_threads() {
+                    scope.spawn(|_| op(self));
+                }
+            }),
+            None => op(self),
+        }
+    }
+
+    /// Creates a single-threaded copy of the engine with an empty transposition table
+    fn worker(&self) -> Engine<F> {
+        let config = EngineConfig {
+            threads: 1,
+            ..self.config.clone()
+        };
+
+        Engine::with_config(self.weights(), config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{Legacy, LegacyWeights, Weights};
+
+    const BOARD_1: Board =
+        Board::from_array([[1, 2, 0, 0], [3, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+
+    /// The values of every move of `board`, analyzed by a new engine
+    fn analysis_values(board: Board, depth: u8, config: &EngineConfig) -> Vec<f32> {
+        let mut engine =
+            Engine::<Legacy>::with_config(LegacyWeights::optimized().unwrap(), config.clone());
+
+        engine
+            .analyze(board, depth)
+            .moves
+            .iter()
+            .map(|analysis| analysis.value)
+            .collect()
+    }
+
+    #[test]
+    fn parallel_search_is_deterministic() {
+        let config = EngineConfig {
+            threads: 8,
+            ..EngineConfig::default()
+        };
+        let canonical_config = EngineConfig {
+            canonical_keys: true,
+            ..config.clone()
+        };
+
+        // The boards on which the symmetric boards of a canonical key used to store different
+        // values
+        for &board in &[0x0212_0001_1000_0000, 0x0000_0010_2030_3510] {
+            let board = Board::from_u64(board);
+
+            for config in &[&config, &canonical_config] {
+                let values = analysis_values(board, 4, config);
+                for _ in 0..4 {
+                    assert_eq!(analysis_values(board, 4, config), values);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_cutoff_is_deterministic() {
+        let config = EngineConfig {
+            threads: 8,
+            prob_cutoff: 0.02,
+            ..EngineConfig::default()
+        };
+
+        // Boards on which the threads used to store the table entries of different paths
+        for &board in &[0x2323_3460_2731_0210, 0x0014_0451_0142_1022] {
+            let board = Board::from_u64(board);
+            let values = analysis_values(board, 5, &config);
+            for _ in 0..4 {
+                assert_eq!(analysis_values(board, 5, &config), values);
+            }
+        }
+    }
+
+    #[test]
+    fn analysis_agrees_with_search() {
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let analysis = engine.analyze(BOARD_1, 2);
+
+        assert_eq!(analysis.moves.len(), BOARD_1.gen_moves().len());
+        assert_eq!(analysis.moves[0].direction, engine.search(BOARD_1, 2));
+        assert_eq!(analysis.stats.nodes_per_depth.len(), 2);
+        assert!(analysis.stats.chance_nodes > 0);
+    }
+
+    #[test]
+    fn parallel_stats_count_every_thread() {
+        let config = EngineConfig {
+            threads: 4,
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let mut parallel_engine =
+            Engine::<Legacy>::with_config(LegacyWeights::optimized().unwrap(), config);
+        // The table isn't probed at depth 2, so both searches visit the same nodes
+        let stats = engine.analyze(BOARD_1, 2).stats;
+        let parallel_stats = parallel_engine.analyze(BOARD_1, 2).stats;
+
+        assert_eq!(parallel_stats.nodes_per_depth, stats.nodes_per_depth);
+        assert_eq!(parallel_stats.chance_nodes, stats.chance_nodes);
+    }
+
+    #[test]
+    fn canonical_keys_hit_more() {
+        let board = Board::from_array([[1, 2, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        let config = EngineConfig {
+            canonical_keys: true,
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let mut canonical_engine =
+            Engine::<Legacy>::with_config(LegacyWeights::optimized().unwrap(), config);
+        // At depth 3, the table is only probed with the afterstates of the root
+        let analysis = engine.analyze(board, 4);
+        let canonical_analysis = canonical_engine.analyze(board, 4);
+
+        assert!(canonical_analysis.table.hit_rate() > analysis.table.hit_rate());
+        assert!(canonical_analysis.stats.nodes() < analysis.stats.nodes());
+    }
+
+    #[test]
+    fn prob_cutoff_prunes() {
+        let config = EngineConfig {
+            prob_cutoff: 0.1,
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let mut pruning_engine =
+            Engine::<Legacy>::with_config(LegacyWeights::optimized().unwrap(), config);
+        let analysis = engine.analyze(BOARD_1, 3);
+        let pruned_analysis = pruning_engine.analyze(BOARD_1, 3);
+
+        assert_eq!(analysis.stats.cutoffs, 0);
+        assert!(pruned_analysis.stats.cutoffs > 0);
+        assert!(pruned_analysis.stats.nodes() < analysis.stats.nodes());
+    }
+
+    #[test]
+    fn search_timed_without_time() {
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let best_move = engine.search_timed(BOARD_1, Duration::from_millis(0));
+
+        assert_eq!(best_move, engine.search(BOARD_1, 1));
+    }
+
+    #[test]
+    fn search_timed_deepens() {
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let best_move = engine.search_timed(BOARD_1, Duration::from_millis(50));
+
+        assert!(BOARD_1.gen_moves().iter().any(|(dir, _)| *dir == best_move));
+        assert!(engine.search_stats().depth >= 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the search depth must be at least 1")]
+    fn zero_depth() {
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        engine.search(BOARD_1, 0);
+    }
 }
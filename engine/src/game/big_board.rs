@@ -0,0 +1,210 @@
+use super::board::{Board, Direction, TileSpawn, DIRECTIONS};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
+use std::vec::Vec;
+
+/// The exponent of the largest tile that can appear in a game, 131072
+pub const MAX_EXPONENT: u64 = 17;
+
+/// The largest exponent that fits in the 4 bits of a tile of `Board`
+const BOARD_MAX_EXPONENT: u8 = 15;
+
+/// A board whose tiles can go past 32768, the largest tile of a `Board`.
+///
+/// It has the same moves as `Board`, but makes them tile by tile instead of with lookup tables,
+/// which is much slower. Games are played on a `BigBoard` so that their scores and tiles are
+/// right, while the engine searches the `Board` given by `to_board_lossy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BigBoard {
+    /// The exponent of every tile, row by row
+    tiles: [u8; 16],
+}
+
+impl BigBoard {
+    pub fn at(self, x: u64, y: u64) -> u64 {
+        u64::from(self.tiles[(y * 4 + x) as usize])
+    }
+
+    /// Returns the board with the tile at `(x, y)` replaced by `exponent`
+    pub fn with_tile(mut self, x: u64, y: u64, exponent: u64) -> BigBoard {
+        assert!(exponent <= MAX_EXPONENT, "the tile fits on a board");
+        self.tiles[(y * 4 + x) as usize] = exponent as u8;
+        self
+    }
+
+    pub fn is_dead(self) -> bool {
+        self.gen_moves().is_empty()
+    }
+
+    pub fn score(self) -> f32 {
+        self.tiles
+            .iter()
+            .filter(|&&tile| tile > 1)
+            .map(|&tile| (u64::from(tile - 1) << tile) as f32)
+            .sum()
+    }
+
+    pub fn highest_tile(self) -> u64 {
+        self.tiles
+            .iter()
+            .map(|&tile| u64::from(tile))
+            .max()
+            .unwrap()
+    }
+
+    pub fn count_empties(self) -> u64 {
+        self.tiles.iter().filter(|&&tile| tile == 0).count() as u64
+    }
+
+    /// Makes a move and spawns a random tile, consuming `rng` exactly like `Board::make_move_with`
+    pub fn make_move_with<R: Rng>(self, direction: Direction, rng: &mut R) -> BigBoard {
+        self.move_candidate(direction).spawn_random_tile(rng)
+    }
+
+    pub fn gen_moves(self) -> Vec<(Direction, BigBoard)> {
+        DIRECTIONS
+            .iter()
+            .map(|&dir| (dir, self.move_candidate(dir)))
+            .filter(|&(_, board)| board != self)
+            .collect()
+    }
+
+    pub fn move_candidate(self, direction: Direction) -> BigBoard {
+        let mut result = BigBoard { tiles: [0; 16] };
+
+        for line in 0..4 {
+            // The cells of the line, starting with the one the tiles slide towards
+            let cells: Vec<usize> = match direction {
+                Direction::Left => (0..4).map(|x| line * 4 + x).collect(),
+                Direction::Right => (0..4).rev().map(|x| line * 4 + x).collect(),
+                Direction::Up => (0..4).map(|y| y * 4 + line).collect(),
+                Direction::Down => (0..4).rev().map(|y| y * 4 + line).collect(),
+            };
+
+            let mut len = 0;
+            // A tile made by a merge can't merge again during the same move
+            let mut merged = false;
+
+            for &cell in &cells {
+                let tile = self.tiles[cell];
+                if tile == 0 {
+                    continue;
+                }
+
+                if len > 0 && !merged && result.tiles[cells[len - 1]] == tile {
+                    result.tiles[cells[len - 1]] += 1;
+                    merged = true;
+                } else {
+                    result.tiles[cells[len]] = tile;
+                    len += 1;
+                    merged = false;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// The boards where a tile spawned, in the same order as `Board::gen_tile_spawns`
+    pub fn gen_tile_spawns(self) -> Vec<(f32, TileSpawn, BigBoard)> {
+        let n = self.count_empties() as f32;
+        let mut results = Vec::new();
+
+        for (i, &tile) in self.tiles.iter().enumerate() {
+            if tile == 0 {
+                for &spawn in &[TileSpawn::Two, TileSpawn::Four] {
+                    let mut board = self;
+                    board.tiles[i] = spawn.exponent() as u8;
+                    results.push((spawn.prob() / n, spawn, board));
+                }
+            }
+        }
+
+        results
+    }
+
+    pub(crate) fn spawn_random_tile<R: Rng>(self, rng: &mut R) -> BigBoard {
+        let tile_spawns = self.gen_tile_spawns();
+        let probabilities: Vec<u32> = tile_spawns
+            .iter()
+            .map(|(_, tile, _)| tile.relative_prob())
+            .collect();
+
+        tile_spawns[WeightedIndex::new(probabilities).unwrap().sample(rng)].2
+    }
+
+    /// The `Board` closest to this board, where the tiles above 32768 are lowered to 32768.
+    ///
+    /// The conversion is exact as long as no tile is above 32768.
+    pub fn to_board_lossy(self) -> Board {
+        let bitboard = self
+            .tiles
+            .iter()
+            .enumerate()
+            .map(|(i, &tile)| u64::from(tile.min(BOARD_MAX_EXPONENT)) << (i * 4))
+            .fold(0, |bitboard, tile| bitboard | tile);
+
+        Board::from_u64(bitboard)
+    }
+}
+
+impl From<Board> for BigBoard {
+    fn from(board: Board) -> BigBoard {
+        let mut tiles = [0; 16];
+        for (i, tile) in tiles.iter_mut().enumerate() {
+            *tile = ((board.into_u64() >> (i * 4)) & 0xF) as u8;
+        }
+
+        BigBoard { tiles }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    const BOARD_1: Board =
+        Board::from_array([[1, 1, 1, 1], [3, 0, 3, 2], [0, 4, 0, 4], [2, 2, 5, 1]]);
+
+    #[test]
+    fn moves_match_board() {
+        for &(dir, board) in &BOARD_1.gen_moves() {
+            assert_eq!(BigBoard::from(BOARD_1).move_candidate(dir), board.into());
+        }
+        assert_eq!(
+            BigBoard::from(BOARD_1).gen_moves().len(),
+            BOARD_1.gen_moves().len()
+        );
+    }
+
+    #[test]
+    fn spawns_match_board() {
+        let mut rng = XorShiftRng::seed_from_u64(4);
+        let mut big_rng = rng.clone();
+
+        let board = BOARD_1.make_move_with(Direction::Up, &mut rng);
+        let big_board = BigBoard::from(BOARD_1).make_move_with(Direction::Up, &mut big_rng);
+        assert_eq!(big_board, board.into());
+    }
+
+    #[test]
+    fn merges_past_32768() {
+        let board = BigBoard::from(Board::from_array([
+            [15, 15, 0, 0],
+            [1, 2, 1, 2],
+            [2, 1, 2, 1],
+            [1, 2, 1, 2],
+        ]));
+        let merged = board.move_candidate(Direction::Left);
+
+        assert_eq!(merged.at(0, 0), 16);
+        assert_eq!(merged.highest_tile(), 16);
+        assert_eq!(board.gen_moves().len(), 3);
+        assert!((merged.score() - board.score() - 65536.).abs() < 1.);
+        assert_eq!(merged.to_board_lossy().at(0, 0), 15);
+    }
+}
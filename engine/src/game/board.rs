@@ -3,12 +3,15 @@ use crate::lookup_table::LookupTable;
 use lazy_static::lazy_static;
 use rand::{
     distributions::{Distribution, WeightedIndex},
-    thread_rng,
+    thread_rng, Rng,
 };
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
 use std::vec::Vec;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     Left,
     Right,
@@ -36,15 +39,25 @@ impl TileSpawn {
             TileSpawn::Four => 1,
         }
     }
+
+    /// The exponent of the spawned tile, as stored in a `Board`
+    pub fn exponent(self) -> u64 {
+        match self {
+            TileSpawn::Two => 1,
+            TileSpawn::Four => 2,
+        }
+    }
 }
 
+/// The directions in the order moves are generated
+pub(crate) const DIRECTIONS: [Direction; 4] = [
+    Direction::Left,
+    Direction::Right,
+    Direction::Up,
+    Direction::Down,
+];
+
 lazy_static! {
-    static ref DIRECTIONS: Vec<Direction> = vec![
-        Direction::Left,
-        Direction::Right,
-        Direction::Up,
-        Direction::Down,
-    ];
     static ref SCORE: LookupTable<f32> = LookupTable::new(|row| row.score());
     static ref MOVES: (
         LookupTable<Row>,
@@ -91,13 +104,22 @@ impl Board {
     }
 
     pub fn new_random() -> Board {
-        Board(0).spawn_random_tile().spawn_random_tile()
+        Board::new_random_with(&mut thread_rng())
+    }
+
+    /// Constructs a board with two random tiles, using `rng` as the source of randomness
+    pub fn new_random_with<R: Rng>(rng: &mut R) -> Board {
+        Board(0).spawn_random_tile(rng).spawn_random_tile(rng)
     }
 
     pub fn from_u64(bitboard: u64) -> Board {
         Board(bitboard)
     }
 
+    pub fn into_u64(self) -> u64 {
+        self.0
+    }
+
     pub fn at(self, x: u64, y: u64) -> u64 {
         let tile_index = y * 4 + x;
         (self.0 >> (tile_index * 4)) & 0xF
@@ -146,21 +168,36 @@ impl Board {
         empties
     }
 
+    /// The number of different tile values on the board
+    pub fn count_distinct_tiles(self) -> u64 {
+        let seen = (0..16)
+            .map(|i| self.at(i % 4, i / 4))
+            .filter(|&tile| tile != 0)
+            .fold(0u16, |seen, tile| seen | (1 << tile));
+
+        u64::from(seen.count_ones())
+    }
+
     pub fn make_move(self, direction: Direction) -> Board {
-        self.move_candidate(direction).spawn_random_tile()
+        self.make_move_with(direction, &mut thread_rng())
+    }
+
+    /// Makes a move and spawns a random tile, using `rng` as the source of randomness
+    pub fn make_move_with<R: Rng>(self, direction: Direction, rng: &mut R) -> Board {
+        self.move_candidate(direction).spawn_random_tile(rng)
     }
 
     pub fn gen_moves(self) -> Vec<(Direction, Board)> {
-        let mut result = Vec::<(Direction, Board)>::with_capacity(4);
+        self.moves().collect()
+    }
 
-        for dir in DIRECTIONS.iter() {
-            let game2 = self.move_candidate(*dir);
-            if self.0 != game2.0 {
-                result.push((*dir, Board(game2.0)))
-            }
+    /// Iterates over the legal moves and their afterstates, like `gen_moves` but without
+    /// allocating
+    pub fn moves(self) -> Moves {
+        Moves {
+            board: self,
+            next: 0,
         }
-
-        result
     }
 
     pub fn move_candidate(self, direction: Direction) -> Board {
@@ -202,8 +239,7 @@ impl Board {
         }
     }
 
-    fn spawn_random_tile(self) -> Board {
-        let mut rng = thread_rng();
+    fn spawn_random_tile<R: Rng>(self, rng: &mut R) -> Board {
         let tile_spawns: Vec<(u32, Board)> = self
             .gen_tile_spawns()
             .into_iter()
@@ -214,33 +250,25 @@ impl Board {
         let resulting_boards: Vec<Board> =
             tile_spawns.iter().map(|tile_spawn| tile_spawn.1).collect();
 
-        resulting_boards[WeightedIndex::new(probabilities).unwrap().sample(&mut rng)]
+        resulting_boards[WeightedIndex::new(probabilities).unwrap().sample(rng)]
     }
 
-    #[allow(clippy::verbose_bit_mask)]
     pub fn gen_tile_spawns(self) -> Vec<(f32, TileSpawn, Board)> {
-        let mut results = Vec::<(f32, TileSpawn, Board)>::new();
-        let n = self.count_empties() as f32;
-
-        for i in 0..16 {
-            if (self.0 >> (i * 4)) & 0xF == 0 {
-                results.push((
-                    TileSpawn::Two.prob() / n,
-                    TileSpawn::Two,
-                    Board(self.0 | 1 << (i * 4)),
-                ));
-                results.push((
-                    TileSpawn::Four.prob() / n,
-                    TileSpawn::Four,
-                    Board(self.0 | 2 << (i * 4)),
-                ));
-            }
-        }
+        self.tile_spawns().collect()
+    }
 
-        results
+    /// Iterates over the boards where a tile spawned with their probabilities, like
+    /// `gen_tile_spawns` but without allocating
+    pub fn tile_spawns(self) -> TileSpawns {
+        TileSpawns {
+            board: self,
+            empties: self.count_empties() as f32,
+            next: 0,
+        }
     }
 
-    fn transposed(self) -> Board {
+    /// The board mirrored along its main diagonal, so that the rows become the columns
+    pub fn transposed(self) -> Board {
         let x = self.0;
         let a1 = x & 0xF0F0_0F0F_F0F0_0F0F;
         let a2 = x & 0x0000_F0F0_0000_F0F0;
@@ -251,6 +279,115 @@ impl Board {
         let b3 = a & 0x0000_0000_FF00_FF00;
         Board::from_u64(b1 | (b2 >> 24) | (b3 << 24))
     }
+
+    /// The board mirrored left to right
+    pub fn mirrored(self) -> Board {
+        let x = self.0;
+        Board(
+            ((x & 0xF000_F000_F000_F000) >> 12)
+                | ((x & 0x0F00_0F00_0F00_0F00) >> 4)
+                | ((x & 0x00F0_00F0_00F0_00F0) << 4)
+                | ((x & 0x000F_000F_000F_000F) << 12),
+        )
+    }
+
+    /// The board rotated a quarter turn clockwise
+    pub fn rotated(self) -> Board {
+        self.transposed().mirrored()
+    }
+
+    /// The 8 rotations and reflections of the board, starting with the board itself
+    pub fn symmetries(self) -> [Board; 8] {
+        let r1 = self.rotated();
+        let r2 = r1.rotated();
+        let r3 = r2.rotated();
+
+        [
+            self,
+            r1,
+            r2,
+            r3,
+            self.mirrored(),
+            r1.mirrored(),
+            r2.mirrored(),
+            r3.mirrored(),
+        ]
+    }
+
+    /// The same board for all the symmetries of a position: the one with the smallest bitboard
+    pub fn canonical(self) -> Board {
+        *self
+            .symmetries()
+            .iter()
+            .min_by_key(|board| board.0)
+            .expect("symmetries has elements")
+    }
+}
+
+/// The iterator returned by `Board::moves`
+#[derive(Debug, Clone)]
+pub struct Moves {
+    board: Board,
+    /// The index in `DIRECTIONS` of the next direction to try
+    next: usize,
+}
+
+impl Iterator for Moves {
+    type Item = (Direction, Board);
+
+    fn next(&mut self) -> Option<(Direction, Board)> {
+        while self.next < DIRECTIONS.len() {
+            let dir = DIRECTIONS[self.next];
+            self.next += 1;
+
+            let afterstate = self.board.move_candidate(dir);
+            if afterstate != self.board {
+                return Some((dir, afterstate));
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(DIRECTIONS.len() - self.next))
+    }
+}
+
+/// The iterator returned by `Board::tile_spawns`
+#[derive(Debug, Clone)]
+pub struct TileSpawns {
+    board: Board,
+    empties: f32,
+    /// Twice the index of the next cell to try, plus one when the 2 was already spawned there
+    next: u64,
+}
+
+impl Iterator for TileSpawns {
+    type Item = (f32, TileSpawn, Board);
+
+    #[allow(clippy::verbose_bit_mask)]
+    fn next(&mut self) -> Option<(f32, TileSpawn, Board)> {
+        while self.next < 32 {
+            let shift = (self.next / 2) * 4;
+            let tile = if self.next % 2 == 0 {
+                TileSpawn::Two
+            } else {
+                TileSpawn::Four
+            };
+            self.next += 1;
+
+            if (self.board.0 >> shift) & 0xF == 0 {
+                return Some((
+                    tile.prob() / self.empties,
+                    tile,
+                    Board(self.board.0 | tile.exponent() << shift),
+                ));
+            }
+        }
+
+        None
+    }
 }
 
 impl fmt::Debug for Board {
@@ -263,6 +400,84 @@ impl fmt::Debug for Board {
     }
 }
 
+/// Writes the board in the grid notation: the rows from top to bottom separated by slashes, with
+/// the values of the tiles separated by spaces and empty cells written as dots, such as
+/// `2 4 . ./8 2 . ./. . . ./. . . 2`
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..4 {
+            if y > 0 {
+                write!(f, "/")?;
+            }
+
+            for x in 0..4 {
+                if x > 0 {
+                    write!(f, " ")?;
+                }
+
+                match self.at(x, y) {
+                    0 => write!(f, ".")?,
+                    exponent => write!(f, "{}", 1u64 << exponent)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBoardError(String);
+
+impl fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid board: {}", self.0)
+    }
+}
+
+impl Error for ParseBoardError {}
+
+/// Reads a board in the grid notation written by `Display`. Empty cells can also be written as
+/// zeros.
+impl FromStr for Board {
+    type Err = ParseBoardError;
+
+    fn from_str(text: &str) -> Result<Board, ParseBoardError> {
+        let rows: Vec<&str> = text.trim().split('/').collect();
+        if rows.len() != 4 {
+            return Err(ParseBoardError(format!(
+                "expected 4 rows, found {}",
+                rows.len()
+            )));
+        }
+
+        let mut tiles = [[0; 4]; 4];
+        for (row, row_tiles) in rows.iter().zip(tiles.iter_mut()) {
+            let values: Vec<&str> = row.split_whitespace().collect();
+            if values.len() != 4 {
+                return Err(ParseBoardError(format!(
+                    "expected 4 tiles in the row \"{}\"",
+                    row.trim()
+                )));
+            }
+
+            for (value, tile) in values.iter().zip(row_tiles.iter_mut()) {
+                *tile = match *value {
+                    "." | "0" => 0,
+                    _ => match value.parse::<u64>() {
+                        Ok(n) if n >= 2 && n <= 1 << 15 && n.is_power_of_two() => {
+                            u64::from(n.trailing_zeros())
+                        }
+                        _ => return Err(ParseBoardError(format!("invalid tile {}", value))),
+                    },
+                };
+            }
+        }
+
+        Ok(Board::from_array(tiles))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,6 +498,39 @@ mod tests {
         assert_eq!(BOARD_1.row_at(3), Row::new(&[12, 13, 14, 15]));
     }
 
+    #[test]
+    fn symmetries() {
+        let board = Board::from_array([[1, 2, 0, 0], [3, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 4]]);
+
+        assert_eq!(
+            board.rotated(),
+            Board::from_array([[0, 0, 3, 1], [0, 0, 0, 2], [0, 0, 0, 0], [4, 0, 0, 0]])
+        );
+        assert_eq!(
+            board.mirrored(),
+            Board::from_array([[0, 0, 2, 1], [0, 0, 0, 3], [0, 0, 0, 0], [4, 0, 0, 0]])
+        );
+        assert_eq!(board.rotated().rotated().rotated().rotated(), board);
+
+        let canonical = board.canonical();
+        assert!(board
+            .symmetries()
+            .iter()
+            .all(|b| b.canonical() == canonical));
+    }
+
+    #[test]
+    fn notation() {
+        let board = Board::from_array([[1, 2, 0, 0], [3, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 15]]);
+        let text = "2 4 . ./8 2 . ./. . . ./. . . 32768";
+
+        assert_eq!(board.to_string(), text);
+        assert_eq!(text.parse::<Board>(), Ok(board));
+        assert_eq!(BOARD_1.to_string().parse::<Board>(), Ok(BOARD_1));
+        assert!("2 4 . ./8 2 . ./. . . .".parse::<Board>().is_err());
+        assert!("3 . . ./. . . ./. . . ./. . . .".parse::<Board>().is_err());
+    }
+
     #[test]
     fn column_at() {
         assert_eq!(BOARD_1.column_at(0), Row::new(&[0, 4, 8, 12]));
@@ -0,0 +1,127 @@
+use super::big_board::BigBoard;
+use super::board::{Board, Direction, TileSpawn};
+use super::recording::Spawn;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+/// A game of 2048 that owns its source of randomness.
+///
+/// Two games created with the same seed spawn the same tiles when given the same moves.
+#[derive(Debug, Clone)]
+pub struct Game {
+    board: BigBoard,
+    rng: XorShiftRng,
+    moves: u64,
+    score: f32,
+    four_spawns: u64,
+}
+
+impl Game {
+    pub fn new(seed: u64) -> Game {
+        let mut rng = XorShiftRng::seed_from_u64(seed);
+        let board = Board::new_random_with(&mut rng);
+
+        Game {
+            board: board.into(),
+            rng,
+            moves: 0,
+            score: 0.,
+            four_spawns: (0..16)
+                .filter(|&i| board.at(i % 4, i / 4) == TileSpawn::Four.exponent())
+                .count() as u64,
+        }
+    }
+
+    /// The board as seen by the engine, where the tiles above 32768 are shown as 32768
+    pub fn board(&self) -> Board {
+        self.board.to_board_lossy()
+    }
+
+    /// The exact board, which can have tiles above 32768
+    pub fn big_board(&self) -> BigBoard {
+        self.board
+    }
+
+    /// The number of moves played so far
+    pub fn moves(&self) -> u64 {
+        self.moves
+    }
+
+    /// The sum of the tiles made by merges so far, which is the score of the game.
+    ///
+    /// `big_board().score()` is derived from the tiles instead, as if every tile had been built
+    /// from 2s, so it also counts 4 points for every 4 that spawned.
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    /// The number of 4s that spawned so far, including the initial tiles
+    pub fn four_spawns(&self) -> u64 {
+        self.four_spawns
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.board.is_dead()
+    }
+
+    /// Plays a move, which must be one of the moves of `big_board().gen_moves()`
+    pub fn make_move(&mut self, direction: Direction) {
+        let afterstate = self.board.move_candidate(direction);
+        let next_board = afterstate.spawn_random_tile(&mut self.rng);
+
+        // The merges are the only change in the tile-derived score between the two boards
+        self.score += afterstate.score() - self.board.score();
+        if let Some(Spawn {
+            tile: TileSpawn::Four,
+            ..
+        }) = Spawn::between(afterstate, next_board)
+        {
+            self.four_spawns += 1;
+        }
+
+        self.board = next_board;
+        self.moves += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_lefts(seed: u64) -> Vec<Board> {
+        let mut game = Game::new(seed);
+        let mut boards = vec![game.board()];
+
+        while let Some(&(dir, _)) = game.board().gen_moves().first() {
+            game.make_move(dir);
+            boards.push(game.board());
+        }
+
+        boards
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn score_skips_spawned_fours() {
+        let mut game = Game::new(11);
+        while let Some(&(dir, _)) = game.big_board().gen_moves().first() {
+            game.make_move(dir);
+        }
+
+        assert!(game.four_spawns() > 0);
+        assert_eq!(
+            game.score(),
+            game.big_board().score() - 4. * game.four_spawns() as f32
+        );
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        assert_eq!(play_lefts(42), play_lefts(42));
+    }
+
+    #[test]
+    fn different_seed_different_game() {
+        assert_ne!(play_lefts(1), play_lefts(2));
+    }
+}
@@ -1,5 +1,11 @@
+mod big_board;
 mod board;
+mod driver;
+mod recording;
 mod row;
 
+pub use self::big_board::*;
 pub use self::board::*;
+pub use self::driver::*;
+pub use self::recording::*;
 pub use self::row::*;
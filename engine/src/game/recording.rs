@@ -0,0 +1,332 @@
+use super::big_board::BigBoard;
+use super::board::{Board, Direction, TileSpawn};
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The version written in the header of recordings. Bumped when the format changes.
+const RECORDING_VERSION: u32 = 1;
+
+/// The first line of a recording
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    seed: Option<u64>,
+    initial_board: u64,
+}
+
+/// Every line of a recording after the header, one per move
+#[derive(Serialize, Deserialize)]
+struct MoveLine {
+    direction: Direction,
+    x: u64,
+    y: u64,
+    /// The value of the spawned tile, 2 or 4
+    tile: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    eval: Option<f32>,
+}
+
+/// A tile that appeared on the board after a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spawn {
+    pub x: u64,
+    pub y: u64,
+    pub tile: TileSpawn,
+}
+
+impl Spawn {
+    /// Finds the tile that spawned on `afterstate` to give `board`, if `board` is one of the
+    /// boards of `afterstate.gen_tile_spawns()`
+    pub fn between(afterstate: BigBoard, board: BigBoard) -> Option<Spawn> {
+        (0..16)
+            .map(|i| (i % 4, i / 4))
+            .find(|&(x, y)| afterstate.at(x, y) != board.at(x, y))
+            .and_then(|(x, y)| {
+                let tile = match board.at(x, y) {
+                    1 => TileSpawn::Two,
+                    2 => TileSpawn::Four,
+                    _ => return None,
+                };
+                let spawn = Spawn { x, y, tile };
+
+                if afterstate.at(x, y) == 0 && spawn.apply(afterstate) == board {
+                    Some(spawn)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Places the tile on `afterstate`, which must have an empty cell at the spawn position
+    fn apply(self, afterstate: BigBoard) -> BigBoard {
+        afterstate.with_tile(self.x, self.y, self.tile.exponent())
+    }
+}
+
+/// A move of a recorded game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedMove {
+    pub direction: Direction,
+    pub spawn: Spawn,
+    /// The expected score the engine gave to the move, if it was recorded
+    pub eval: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The recording is malformed, or holds a move that can't be played
+    Format(String),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "could not access the recording: {}", err),
+            RecordingError::Json(err) => write!(f, "invalid recording: {}", err),
+            RecordingError::Format(err) => write!(f, "invalid recording: {}", err),
+        }
+    }
+}
+
+impl Error for RecordingError {}
+
+impl From<io::Error> for RecordingError {
+    fn from(err: io::Error) -> RecordingError {
+        RecordingError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for RecordingError {
+    fn from(err: serde_json::Error) -> RecordingError {
+        RecordingError::Json(err)
+    }
+}
+
+/// A game saved move by move, so that it can be replayed exactly.
+///
+/// Recordings are stored as JSON lines: a header with the initial board, followed by one line
+/// per move with its direction, the position and value of the tile that spawned after it, and
+/// optionally the engine's evaluation of the move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// The seed of the game, when it was played with `Game`
+    pub seed: Option<u64>,
+    pub initial_board: Board,
+    pub moves: Vec<RecordedMove>,
+}
+
+impl Recording {
+    pub fn new(initial_board: Board, seed: Option<u64>) -> Recording {
+        Recording {
+            seed,
+            initial_board,
+            moves: Vec::new(),
+        }
+    }
+
+    /// Records a move played from `board`, the last board of the recording, which led to
+    /// `next_board`.
+    ///
+    /// Panics if `next_board` can't be reached by playing `direction` and spawning a tile.
+    pub fn push(
+        &mut self,
+        board: BigBoard,
+        direction: Direction,
+        next_board: BigBoard,
+        eval: Option<f32>,
+    ) {
+        let afterstate = board.move_candidate(direction);
+        let spawn =
+            Spawn::between(afterstate, next_board).expect("the next board follows the move");
+
+        self.moves.push(RecordedMove {
+            direction,
+            spawn,
+            eval,
+        });
+    }
+
+    /// Every board of the game, starting with the initial board
+    pub fn boards(&self) -> Vec<BigBoard> {
+        let mut boards = Vec::with_capacity(self.moves.len() + 1);
+        let mut board = BigBoard::from(self.initial_board);
+        boards.push(board);
+
+        for mov in &self.moves {
+            board = mov.spawn.apply(board.move_candidate(mov.direction));
+            boards.push(board);
+        }
+
+        boards
+    }
+
+    pub fn final_board(&self) -> BigBoard {
+        let initial_board = BigBoard::from(self.initial_board);
+        self.moves.iter().fold(initial_board, |board, mov| {
+            mov.spawn.apply(board.move_candidate(mov.direction))
+        })
+    }
+
+    /// The sum of the tiles made by merges during the game
+    pub fn score(&self) -> f32 {
+        let mut board = BigBoard::from(self.initial_board);
+        let mut score = 0.;
+
+        for mov in &self.moves {
+            let afterstate = board.move_candidate(mov.direction);
+            score += afterstate.score() - board.score();
+            board = mov.spawn.apply(afterstate);
+        }
+
+        score
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, RecordingError> {
+        Recording::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a recording, checking that every move can be played
+    pub fn read_from(reader: impl BufRead) -> Result<Recording, RecordingError> {
+        let mut lines = reader.lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(RecordingError::Format("the file is empty".to_string())),
+        };
+
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::Format(format!(
+                "unsupported version {}",
+                header.version
+            )));
+        }
+
+        let mut recording = Recording::new(Board::from_u64(header.initial_board), header.seed);
+        let mut board = BigBoard::from(recording.initial_board);
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mov: MoveLine = serde_json::from_str(&line)?;
+            let error =
+                |reason: &str| RecordingError::Format(format!("move {}: {}", i + 1, reason));
+
+            let afterstate = board.move_candidate(mov.direction);
+            if afterstate == board {
+                return Err(error("the move doesn't change the board"));
+            }
+
+            let tile = match mov.tile {
+                2 => TileSpawn::Two,
+                4 => TileSpawn::Four,
+                _ => return Err(error("the spawned tile is neither a 2 nor a 4")),
+            };
+            if mov.x >= 4 || mov.y >= 4 || afterstate.at(mov.x, mov.y) != 0 {
+                return Err(error("the tile spawns outside of an empty cell"));
+            }
+
+            let spawn = Spawn {
+                x: mov.x,
+                y: mov.y,
+                tile,
+            };
+            board = spawn.apply(afterstate);
+            recording.moves.push(RecordedMove {
+                direction: mov.direction,
+                spawn,
+                eval: mov.eval,
+            });
+        }
+
+        Ok(recording)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), RecordingError> {
+        let header = Header {
+            version: RECORDING_VERSION,
+            seed: self.seed,
+            initial_board: self.initial_board.into_u64(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
+
+        for mov in &self.moves {
+            let line = MoveLine {
+                direction: mov.direction,
+                x: mov.spawn.x,
+                y: mov.spawn.y,
+                tile: 1 << mov.spawn.tile.exponent(),
+                eval: mov.eval,
+            };
+            serde_json::to_writer(&mut writer, &line)?;
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    fn record_lefts(seed: u64) -> (Recording, Game) {
+        let mut game = Game::new(seed);
+        let mut recording = Recording::new(game.board(), Some(seed));
+
+        while let Some(&(dir, _)) = game.board().gen_moves().first() {
+            let board = game.big_board();
+            game.make_move(dir);
+            recording.push(board, dir, game.big_board(), Some(game.moves() as f32));
+        }
+
+        (recording, game)
+    }
+
+    #[test]
+    fn replays_the_game() {
+        let (recording, game) = record_lefts(3);
+        assert_eq!(recording.final_board(), game.big_board());
+        assert_eq!(recording.moves.len() as u64, game.moves());
+    }
+
+    #[test]
+    fn round_trip() {
+        let (recording, _) = record_lefts(3);
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes).unwrap();
+
+        assert_eq!(Recording::read_from(&bytes[..]).unwrap(), recording);
+    }
+
+    #[test]
+    fn rejects_impossible_spawns() {
+        let board = Board::from_array([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+        let file = format!(
+            "{{\"version\":1,\"seed\":null,\"initial_board\":{}}}\n\
+             {{\"direction\":\"Left\",\"x\":0,\"y\":0,\"tile\":2}}\n",
+            board.into_u64()
+        );
+
+        match Recording::read_from(file.as_bytes()) {
+            Err(RecordingError::Format(_)) => (),
+            result => panic!("expected a format error, got {:?}", result),
+        }
+    }
+}
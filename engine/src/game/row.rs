@@ -51,29 +51,25 @@ impl Row {
     }
 
     pub fn moved(self) -> Row {
-        let mut tiles: Vec<u16> = (0..4).map(|i| self.tile_at(i)).collect();
-
-        let mut changed = true;
-        while changed {
-            changed = false;
-
-            for i in 0..3 {
-                if tiles[i] == 0 && tiles[i + 1] != 0 {
-                    tiles[i] = tiles[i + 1];
-                    tiles[i + 1] = 0;
-                    changed = true;
-                } else if tiles[i] != 0 && tiles[i] == tiles[i + 1] {
-                    // Pretend that two 15 tiles can't be merged
-                    if tiles[i] != 15 {
-                        tiles[i] += 1;
-                        tiles[i + 1] = 0;
-                        changed = true;
-                    }
-                }
+        let mut tiles = [0; 4];
+        let mut len = 0;
+        // A tile made by a merge can't merge again during the same move
+        let mut merged = false;
+
+        for tile in (0..4).map(|i| self.tile_at(i)).filter(|&tile| tile != 0) {
+            // Pretend that two 15 tiles can't be merged, since a 16 doesn't fit in 4 bits.
+            // `BigBoard` merges them.
+            if len > 0 && !merged && tiles[len - 1] == tile && tile != 15 {
+                tiles[len - 1] += 1;
+                merged = true;
+            } else {
+                tiles[len] = tile;
+                len += 1;
+                merged = false;
             }
         }
 
-        Row::new(tiles.as_slice())
+        Row::new(&tiles)
     }
 
     pub fn reversed(self) -> Row {
@@ -133,7 +129,7 @@ impl fmt::Debug for Row {
 fn repr_tile(tile: u16) -> u64 {
     match tile {
         0 => 0,
-        n if 1 <= n && n <= 15 => 1 << n,
+        1..=15 => 1 << tile,
         _ => unreachable!(),
     }
 }
@@ -159,6 +155,8 @@ mod tests {
         assert_eq!(Row::new(&[1, 1, 0, 0]).moved(), Row::new(&[2, 0, 0, 0]));
         assert_eq!(Row::new(&[0, 0, 1, 0]).moved(), Row::new(&[1, 0, 0, 0]));
         assert_eq!(Row::new(&[0, 1, 3, 3]).moved(), Row::new(&[1, 4, 0, 0]));
+        assert_eq!(Row::new(&[1, 1, 1, 1]).moved(), Row::new(&[2, 2, 0, 0]));
+        assert_eq!(Row::new(&[2, 1, 1, 0]).moved(), Row::new(&[2, 2, 0, 0]));
     }
 
     #[test]
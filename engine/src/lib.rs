@@ -1,11 +1,16 @@
+mod depth_policy;
 mod engine;
 mod game;
 mod lookup_table;
+mod search_stats;
 pub mod testing;
 mod training;
 mod transposition_table;
 pub mod v_function;
 
+pub use crate::depth_policy::{AdaptiveDepth, DepthPolicy};
 pub use crate::engine::*;
 pub use crate::game::*;
+pub use crate::search_stats::SearchStats;
 pub use crate::training::*;
+pub use crate::transposition_table::{Replacement, TableStats};
@@ -0,0 +1,139 @@
+use serde_derive::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// What a search did, as returned by `Engine::search_stats`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchStats {
+    /// The depth of the search, or of the deepest iteration that finished for a timed search
+    pub depth: u8,
+    /// The number of nodes visited at each remaining depth: the afterstates of the root are
+    /// searched at `depth - 1`, and the static evaluations are at 0
+    pub nodes_per_depth: Vec<u64>,
+    /// The number of nodes where a tile spawns, without the static evaluations
+    pub chance_nodes: u64,
+    /// The number of chance nodes evaluated statically because they were too unlikely, see
+    /// `EngineConfig::prob_cutoff`
+    pub cutoffs: u64,
+    /// The number of evaluations found in the transposition table
+    pub table_hits: u64,
+    /// The time taken by the search, in seconds
+    pub elapsed: f32,
+}
+
+impl SearchStats {
+    /// The total number of nodes visited
+    pub fn nodes(&self) -> u64 {
+        self.nodes_per_depth.iter().sum()
+    }
+
+    /// The branching factor of a uniform tree as deep as the search with as many nodes
+    pub fn branching_factor(&self) -> f32 {
+        if self.depth == 0 {
+            0.
+        } else {
+            (self.nodes() as f32).powf(1. / f32::from(self.depth))
+        }
+    }
+}
+
+/// Counts the nodes visited by all the threads of a search.
+///
+/// Every thread has its own row of counters, which are summed when the search is over, so that
+/// the threads don't contend for the same counters.
+pub(crate) struct NodeCounters {
+    /// The counters of each thread: one per depth, then the chance nodes and the cutoffs
+    counters: Vec<Counter>,
+    width: usize,
+    threads: usize,
+    start: Instant,
+}
+
+/// A counter alone on its cache line, so that incrementing it doesn't slow down the threads
+/// incrementing their own counters
+#[repr(align(64))]
+#[derive(Default)]
+struct Counter(AtomicU64);
+
+impl Counter {
+    fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl NodeCounters {
+    /// Starts counting for a search that goes at most `max_depth` deep on `threads` threads
+    pub fn new(max_depth: u8, threads: usize) -> NodeCounters {
+        let width = usize::from(max_depth) + 3;
+
+        NodeCounters {
+            counters: (0..width * threads).map(|_| Counter::default()).collect(),
+            width,
+            threads,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn count_move_node(&self, depth: u8) {
+        self.count_depth(depth);
+    }
+
+    pub fn count_chance_node(&self, depth: u8) {
+        self.count_depth(depth);
+        if depth > 0 {
+            self.row()[self.width - 2].increment();
+        }
+    }
+
+    pub fn count_cutoff(&self) {
+        self.row()[self.width - 1].increment();
+    }
+
+    /// The statistics of the search so far, which finished an iteration at `depth`
+    pub fn stats(&self, depth: u8, table_hits: u64) -> SearchStats {
+        let elapsed = self.start.elapsed();
+        let mut totals = vec![0; self.width];
+        for row in self.counters.chunks(self.width) {
+            for (total, counter) in totals.iter_mut().zip(row) {
+                *total += counter.0.load(Ordering::Relaxed);
+            }
+        }
+
+        let cutoffs = totals.pop().unwrap();
+        let chance_nodes = totals.pop().unwrap();
+        let mut nodes_per_depth = totals;
+
+        // Only keep the depths the search reached
+        while nodes_per_depth.last() == Some(&0) {
+            nodes_per_depth.pop();
+        }
+
+        SearchStats {
+            depth,
+            nodes_per_depth,
+            chance_nodes,
+            cutoffs,
+            table_hits,
+            elapsed: elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9,
+        }
+    }
+
+    /// Counts a node at `depth`, ignoring the depths deeper than the search announced
+    fn count_depth(&self, depth: u8) {
+        if usize::from(depth) < self.width - 2 {
+            self.row()[usize::from(depth)].increment();
+        }
+    }
+
+    /// The counters of the calling thread. A single-threaded search may run on a thread of
+    /// another pool, so its counters don't depend on the thread.
+    fn row(&self) -> &[Counter] {
+        let thread = if self.threads > 1 {
+            rayon::current_thread_index().map_or(0, |thread| thread % self.threads)
+        } else {
+            0
+        };
+
+        &self.counters[thread * self.width..(thread + 1) * self.width]
+    }
+}
@@ -0,0 +1,227 @@
+use super::benchmark;
+use crate::engine::{Engine, SearchLimit};
+use crate::v_function::VFunction;
+use serde_derive::{Deserialize, Serialize};
+use statistical::{mean, standard_deviation, univariate::standard_error_mean};
+
+/// The probability of declaring the wrong engine stronger, for both engines
+const SPRT_ERROR: f32 = 0.05;
+/// The number of seeds played by both engines between two checks of the sequential test
+const BATCH_SIZE: u64 = 10;
+/// The sequential test isn't checked before this many seeds, so that the variance of the score
+/// differences is estimated reliably
+const MIN_SPRT_GAMES: u64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct CompareConfig {
+    /// The number of seeds after which the comparison stops, even when it's inconclusive
+    pub max_games: u64,
+    /// The smallest average score difference worth detecting. The sequential test weighs "A is
+    /// stronger by `margin`" against "B is stronger by `margin`".
+    pub margin: f32,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    AStronger,
+    BStronger,
+    /// The maximum number of games was reached before the test was significant
+    Inconclusive,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComparisonResult {
+    /// The number of seeds played by both engines
+    pub games: u64,
+    pub average_a: f32,
+    pub average_b: f32,
+    /// The average of the score of A minus the score of B on the same seed
+    pub difference: f32,
+    pub error: f32,
+    pub lower_bound: f32,
+    pub upper_bound: f32,
+    /// The log-likelihood ratio of the sequential test, positive when A looks stronger
+    pub llr: f32,
+    pub verdict: Verdict,
+}
+
+/// Plays both engines on the same seeds, `seed + i` for the i-th game, and compares their scores
+/// game by game. Pairing the games cancels out the luck of the spawns, so far fewer games are
+/// needed than with two separate benchmarks.
+///
+/// The games are played in batches, and the comparison stops as soon as a sequential probability
+/// ratio test (SPRT) on the score differences is significant. `on_progress` is called with the
+/// number of seeds played by both engines after every batch.
+pub fn compare<A, B, P>(
+    engine_a: &Engine<A>,
+    limit_a: SearchLimit,
+    engine_b: &Engine<B>,
+    limit_b: SearchLimit,
+    config: &CompareConfig,
+    on_progress: P,
+) -> ComparisonResult
+where
+    A: VFunction,
+    B: VFunction,
+    P: Fn(u64),
+{
+    let mut scores_a = Vec::with_capacity(config.max_games as usize);
+    let mut scores_b = Vec::with_capacity(config.max_games as usize);
+    let mut differences: Vec<f32> = Vec::with_capacity(config.max_games as usize);
+    let mut llr = 0.;
+    let mut verdict = Verdict::Inconclusive;
+
+    while (differences.len() as u64) < config.max_games {
+        let played = differences.len() as u64;
+        let batch_size = BATCH_SIZE.min(config.max_games - played);
+        let seed = config.seed.wrapping_add(played);
+
+        let games_a = benchmark(engine_a, batch_size, limit_a.clone(), seed, |_| ()).games;
+        let games_b = benchmark(engine_b, batch_size, limit_b.clone(), seed, |_| ()).games;
+
+        for (a, b) in games_a.iter().zip(&games_b) {
+            scores_a.push(a.score);
+            scores_b.push(b.score);
+            differences.push(a.score - b.score);
+        }
+
+        on_progress(differences.len() as u64);
+
+        if differences.len() as u64 >= MIN_SPRT_GAMES {
+            llr = log_likelihood_ratio(&differences, config.margin);
+
+            if let Some(significant) = sprt_verdict(llr) {
+                verdict = significant;
+                break;
+            }
+        }
+    }
+
+    let difference = mean(&differences);
+    let sd = if differences.len() > 1 {
+        standard_deviation(&differences, Some(difference))
+    } else {
+        0.
+    };
+    let error = standard_error_mean(sd, differences.len() as f32, None);
+
+    ComparisonResult {
+        games: differences.len() as u64,
+        average_a: mean(&scores_a),
+        average_b: mean(&scores_b),
+        difference,
+        error,
+        lower_bound: difference - 1.96 * error,
+        upper_bound: difference + 1.96 * error,
+        llr,
+        verdict,
+    }
+}
+
+/// The verdict of the sequential test once its log-likelihood ratio reaches one of Wald's
+/// bounds, or `None` while it must keep playing
+fn sprt_verdict(llr: f32) -> Option<Verdict> {
+    if llr >= ((1. - SPRT_ERROR) / SPRT_ERROR).ln() {
+        Some(Verdict::AStronger)
+    } else if llr <= (SPRT_ERROR / (1. - SPRT_ERROR)).ln() {
+        Some(Verdict::BStronger)
+    } else {
+        None
+    }
+}
+
+/// The log-likelihood ratio of "the differences have a mean of `margin`" against "they have a
+/// mean of `-margin`", modelling the differences as normal with their sample variance
+fn log_likelihood_ratio(differences: &[f32], margin: f32) -> f32 {
+    let sum: f32 = differences.iter().sum();
+    let variance = standard_deviation(differences, None).powi(2);
+
+    if variance > 0. {
+        2. * margin * sum / variance
+    } else if sum == 0. {
+        0.
+    } else {
+        // Every game ended with the same non-zero difference
+        sum.signum() * f32::INFINITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{Legacy, LegacyWeights, Weights};
+    use approx::*;
+
+    fn config(max_games: u64) -> CompareConfig {
+        CompareConfig {
+            max_games,
+            margin: 100.,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn llr_follows_the_differences() {
+        // A sum of 4 and a sample variance of 2
+        assert_relative_eq!(log_likelihood_ratio(&[1., 3.], 10.), 40.);
+        assert_relative_eq!(log_likelihood_ratio(&[-1., -3.], 10.), -40.);
+        assert_relative_eq!(log_likelihood_ratio(&[-2., 2.], 10.), 0.);
+    }
+
+    #[test]
+    fn llr_of_constant_differences() {
+        assert_eq!(log_likelihood_ratio(&[5., 5., 5.], 10.), f32::INFINITY);
+        assert_eq!(log_likelihood_ratio(&[-5., -5.], 10.), f32::NEG_INFINITY);
+        assert_relative_eq!(log_likelihood_ratio(&[0., 0.], 10.), 0.);
+    }
+
+    #[test]
+    fn sprt_bounds() {
+        // Wald's bounds for errors of 5% are ±ln(19) ≈ ±2.944
+        assert_eq!(sprt_verdict(2.95), Some(Verdict::AStronger));
+        assert_eq!(sprt_verdict(2.94), None);
+        assert_eq!(sprt_verdict(0.), None);
+        assert_eq!(sprt_verdict(-2.94), None);
+        assert_eq!(sprt_verdict(-2.95), Some(Verdict::BStronger));
+    }
+
+    #[test]
+    fn sprt_detects_a_margin() {
+        let differences: Vec<f32> = (0..MIN_SPRT_GAMES)
+            .map(|i| if i % 2 == 0 { 0. } else { 400. })
+            .collect();
+
+        let llr = log_likelihood_ratio(&differences, 100.);
+        assert_eq!(sprt_verdict(llr), Some(Verdict::AStronger));
+
+        let reversed: Vec<f32> = differences.iter().map(|difference| -difference).collect();
+        let llr = log_likelihood_ratio(&reversed, 100.);
+        assert_eq!(sprt_verdict(llr), Some(Verdict::BStronger));
+    }
+
+    #[test]
+    fn same_engine_is_inconclusive() {
+        let engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let limit = SearchLimit::Depth(1);
+        let result = compare(&engine, limit.clone(), &engine, limit, &config(2), |_| ());
+
+        assert_eq!(result.games, 2);
+        assert_eq!(result.verdict, Verdict::Inconclusive);
+        assert_relative_eq!(result.difference, 0.);
+    }
+
+    /// Plays dozens of full games, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn stronger_engine_wins() {
+        let trained = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let blank = Engine::<Legacy>::new(LegacyWeights::default());
+        let limit = SearchLimit::Depth(1);
+        let result = compare(&blank, limit.clone(), &trained, limit, &config(200), |_| ());
+
+        assert_eq!(result.verdict, Verdict::BStronger);
+        assert!(result.games < 200);
+        assert!(result.upper_bound < 0.);
+    }
+}
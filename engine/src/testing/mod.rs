@@ -0,0 +1,285 @@
+use crate::engine::{Engine, SearchLimit};
+use crate::game::{Game, Recording, MAX_EXPONENT};
+use crate::search_stats::SearchStats;
+use crate::v_function::VFunction;
+use serde_derive::{Deserialize, Serialize};
+use statistical::{mean, standard_deviation, univariate::standard_error_mean};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+mod compare;
+
+pub use self::compare::*;
+
+/// Plays a game from start to finish. The tile spawns are entirely determined by `seed`.
+///
+/// `on_board` is called with the game when it starts and after every move, along with the
+/// statistics of the search that picked the move. There are no statistics for the initial board
+/// and for the moves played without searching.
+pub fn play_random_game(
+    engine: &mut Engine<impl VFunction>,
+    limit: SearchLimit,
+    seed: u64,
+    on_board: impl FnMut(&Game, Option<&SearchStats>),
+) -> Game {
+    play_game(engine, limit, seed, on_board, None)
+}
+
+/// Plays a game like `play_random_game`, recording every move along with the engine's
+/// evaluation of it
+pub fn play_recorded_game(
+    engine: &mut Engine<impl VFunction>,
+    limit: SearchLimit,
+    seed: u64,
+    on_board: impl FnMut(&Game, Option<&SearchStats>),
+) -> (Game, Recording) {
+    let mut recording = Recording::new(Game::new(seed).board(), Some(seed));
+    let game = play_game(engine, limit, seed, on_board, Some(&mut recording));
+
+    (game, recording)
+}
+
+fn play_game(
+    engine: &mut Engine<impl VFunction>,
+    limit: SearchLimit,
+    seed: u64,
+    mut on_board: impl FnMut(&Game, Option<&SearchStats>),
+    mut recording: Option<&mut Recording>,
+) -> Game {
+    let mut game = Game::new(seed);
+    on_board(&game, None);
+
+    while !game.is_dead() {
+        let board = game.big_board();
+        let searched = !game.board().is_dead();
+        let (mov, eval) = if searched {
+            engine.search_with_eval(game.board(), limit.clone())
+        } else {
+            // The only moves left merge two 32768 tiles, which the engine's boards can't show
+            (board.gen_moves()[0].0, 0.)
+        };
+        game.make_move(mov);
+
+        if let Some(recording) = recording.as_mut() {
+            recording.push(board, mov, game.big_board(), Some(eval));
+        }
+
+        on_board(&game, Some(engine.search_stats()).filter(|_| searched));
+    }
+
+    game
+}
+
+/// Plays `num_games` games. The i-th game is played with the seed `seed + i`, so two benchmarks
+/// with the same seed play on identical spawn sequences.
+///
+/// The games are spread over the engine's threads, each thread playing one game at a time with
+/// its own copy of the engine. `on_progress` is called with the index of every finished game.
+///
+/// Panics if `num_games` is 0, since the statistics of no games are undefined.
+pub fn benchmark<F>(
+    engine: &Engine<impl VFunction>,
+    num_games: u64,
+    limit: SearchLimit,
+    seed: u64,
+    on_progress: F,
+) -> BenchmarkResult
+where
+    F: Fn(u64) + Sync,
+{
+    assert!(num_games > 0, "a benchmark must play at least one game");
+
+    let next_game = AtomicU64::new(0);
+    let finished_games = Mutex::new(Vec::with_capacity(num_games as usize));
+
+    engine.for_each_worker(|worker| loop {
+        let i = next_game.fetch_add(1, Ordering::Relaxed);
+        if i >= num_games {
+            break;
+        }
+
+        let game_seed = seed.wrapping_add(i);
+        let start = Instant::now();
+        let game = play_random_game(worker, limit.clone(), game_seed, |_, _| ());
+        let elapsed = start.elapsed();
+        worker.reset();
+
+        let record = GameRecord {
+            seed: game_seed,
+            score: game.score(),
+            max_tile: 1 << game.big_board().highest_tile(),
+            moves: game.moves(),
+            four_spawns: game.four_spawns(),
+            seconds: elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9,
+        };
+
+        finished_games
+            .lock()
+            .unwrap()
+            .push((i, record, game.big_board()));
+        on_progress(i);
+    });
+
+    let mut finished_games = finished_games.into_inner().unwrap();
+    finished_games.sort_by_key(|(i, _, _)| *i);
+
+    let mut games = Vec::with_capacity(num_games as usize);
+    let mut tiles_reached_count = [0u64; MAX_EXPONENT as usize + 1];
+
+    for (_, record, board) in finished_games {
+        games.push(record);
+
+        for j in 0..=board.highest_tile() {
+            tiles_reached_count[j as usize] += 1;
+        }
+    }
+
+    let scores: Vec<f32> = games.iter().map(|game| game.score).collect();
+    let mut sorted_scores = scores.clone();
+    sorted_scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let average = mean(scores.as_slice());
+    // The standard deviation is undefined with a single game
+    let sd = if scores.len() > 1 {
+        standard_deviation(scores.as_slice(), Some(average))
+    } else {
+        0.
+    };
+    let error = standard_error_mean(sd, scores.len() as f32, None);
+    let lower_bound = average - 1.96 * error;
+    let upper_bound = average + 1.96 * error;
+
+    let mut tiles_reached = [0.; MAX_EXPONENT as usize + 1];
+    for (reached, &count) in tiles_reached.iter_mut().zip(&tiles_reached_count) {
+        *reached = (count as f32) / (num_games as f32);
+    }
+
+    BenchmarkResult {
+        average,
+        standard_deviation: sd,
+        error,
+        lower_bound,
+        upper_bound,
+        median: percentile(&sorted_scores, 0.5),
+        p10: percentile(&sorted_scores, 0.1),
+        p25: percentile(&sorted_scores, 0.25),
+        p75: percentile(&sorted_scores, 0.75),
+        p90: percentile(&sorted_scores, 0.9),
+        min: sorted_scores[0],
+        max: sorted_scores[sorted_scores.len() - 1],
+        tiles_reached,
+        games,
+    }
+}
+
+/// The score below which a fraction `p` of the scores fall, interpolated between the two
+/// closest scores
+fn percentile(sorted_scores: &[f32], p: f32) -> f32 {
+    let rank = p * (sorted_scores.len() - 1) as f32;
+    let lower = sorted_scores[rank.floor() as usize];
+    let upper = sorted_scores[rank.ceil() as usize];
+
+    lower + (upper - lower) * rank.fract()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub average: f32,
+    pub standard_deviation: f32,
+    pub error: f32,
+    pub lower_bound: f32,
+    pub upper_bound: f32,
+    pub median: f32,
+    /// The 10th percentile of the scores
+    pub p10: f32,
+    pub p25: f32,
+    pub p75: f32,
+    pub p90: f32,
+    pub min: f32,
+    pub max: f32,
+    pub tiles_reached: [f32; MAX_EXPONENT as usize + 1],
+    /// Every game, in the order of their seeds
+    pub games: Vec<GameRecord>,
+}
+
+/// The outcome of a single benchmark game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub score: f32,
+    /// The value of the highest tile, like 2048
+    pub max_tile: u64,
+    pub moves: u64,
+    /// The number of 4s that spawned during the game
+    pub four_spawns: u64,
+    /// The time taken to play the game, in seconds
+    pub seconds: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depth_policy::{AdaptiveDepth, DepthPolicy};
+    use crate::engine::EngineConfig;
+    use crate::v_function::{Legacy, LegacyWeights, Weights};
+    use approx::*;
+    use std::sync::Arc;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn parallel_benchmark() {
+        let engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let parallel_engine = Engine::<Legacy>::with_config(
+            LegacyWeights::optimized().unwrap(),
+            EngineConfig {
+                threads: 3,
+                ..EngineConfig::default()
+            },
+        );
+
+        let result = benchmark(&engine, 4, SearchLimit::Depth(1), 0, |_| ());
+        let parallel_result = benchmark(&parallel_engine, 4, SearchLimit::Depth(1), 0, |_| ());
+
+        assert_eq!(result.average, parallel_result.average);
+        assert_eq!(result.tiles_reached, parallel_result.tiles_reached);
+    }
+
+    #[test]
+    fn adaptive_game() {
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::optimized().unwrap());
+        let policy = AdaptiveDepth {
+            min_depth: 1,
+            max_depth: 2,
+        };
+
+        let mut board = Game::new(0).board();
+        let mut depths = Vec::new();
+        play_random_game(
+            &mut engine,
+            SearchLimit::Adaptive(Arc::new(policy)),
+            0,
+            |game, stats| {
+                if let Some(stats) = stats {
+                    assert_eq!(stats.depth, policy.depth(board));
+                    depths.push(stats.depth);
+                }
+                board = game.board();
+            },
+        );
+
+        // The game goes from open boards to crowded ones
+        assert!(depths.contains(&1));
+        assert!(depths.contains(&2));
+    }
+
+    #[test]
+    fn percentiles() {
+        let scores = [1., 2., 3., 4., 5.];
+
+        assert_relative_eq!(percentile(&scores, 0.5), 3.);
+        assert_relative_eq!(percentile(&scores, 0.25), 2.);
+        assert_relative_eq!(percentile(&scores, 0.1), 1.4);
+        assert_relative_eq!(percentile(&scores[..1], 0.9), 1.);
+    }
+}
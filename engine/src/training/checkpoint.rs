@@ -0,0 +1,209 @@
+//! Checkpoints of training runs.
+//!
+//! A checkpoint starts with the weights as an uncompressed binary weights file, so it can also be
+//! loaded as plain weights. The state of the run is appended to them, and all the integers and
+//! floats are little-endian:
+//!
+//! | Field       | Encoding                                                    |
+//! |-------------|-------------------------------------------------------------|
+//! | weights     | a binary weights file                                       |
+//! | run         | `u32` length followed by the JSON training config and state |
+//! | coherence   | `u32` number of tables, followed by the `u32` size of each  |
+//! | data        | every coherence table, as pairs of `f32`s                   |
+//! | run length  | `u64` length of the run, coherence and data fields          |
+
+use super::{TrainingConfig, TrainingState};
+use crate::v_function::{binary_weights, weights_file, Coherence, Weights, WeightsError};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct RunRef<'a> {
+    config: &'a TrainingConfig,
+    state: &'a TrainingState,
+}
+
+#[derive(Deserialize)]
+struct Run {
+    config: TrainingConfig,
+    state: TrainingState,
+}
+
+/// A snapshot of a training run, from which the run can be resumed with `resume_td`
+#[derive(Debug, Clone)]
+pub struct Checkpoint<W> {
+    pub weights: W,
+    pub config: TrainingConfig,
+    pub state: TrainingState,
+}
+
+impl<W: Weights> Checkpoint<W> {
+    pub fn load(path: impl AsRef<Path>) -> Result<Checkpoint<W>, WeightsError> {
+        let bytes = fs::read(path)?;
+        let truncated = || WeightsError::Format("truncated checkpoint".to_owned());
+
+        let footer = bytes.len().checked_sub(8).ok_or_else(truncated)?;
+        let run_len = u64::from_le_bytes(read_array(&bytes[footer..])) as usize;
+        let weights_len = footer.checked_sub(run_len).ok_or_else(truncated)?;
+
+        let weights = weights_file::from_slice(&bytes[..weights_len])?;
+
+        let mut rest = &bytes[weights_len..footer];
+        let json_len = u32::from_le_bytes(read_array(take(&mut rest, 4)?)) as usize;
+        let run: Run = serde_json::from_slice(take(&mut rest, json_len)?)?;
+
+        // Reading the sizes before allocating anything bounds the allocations by the file's length
+        let num_tables = u32::from_le_bytes(read_array(take(&mut rest, 4)?)) as usize;
+        let shape: Vec<usize> = take(&mut rest, num_tables * 4)?
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes(read_array(bytes)) as usize)
+            .collect();
+
+        let mut tables = Vec::with_capacity(num_tables);
+        for size in shape {
+            let data = take(&mut rest, size * 8)?;
+            let table = data
+                .chunks(8)
+                .map(|bytes| {
+                    let net = f32::from_le_bytes(read_array(&bytes[..4]));
+                    let absolute = f32::from_le_bytes(read_array(&bytes[4..]));
+                    (net, absolute)
+                })
+                .collect();
+            tables.push(table);
+        }
+
+        let mut state = run.state;
+        state.coherence = Coherence::from_tables(tables);
+
+        Ok(Checkpoint {
+            weights,
+            config: run.config,
+            state,
+        })
+    }
+}
+
+/// Saves a checkpoint. The file is written next to `path` first, so that an interrupted save
+/// leaves the previous checkpoint intact.
+pub fn save<W: Weights>(
+    path: &Path,
+    weights: &W,
+    config: &TrainingConfig,
+    state: &TrainingState,
+) -> Result<(), WeightsError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let run = serde_json::to_vec(&RunRef { config, state })?;
+    let tables = state.coherence.tables();
+    let run_len = 4
+        + run.len()
+        + 4
+        + tables
+            .iter()
+            .map(|table| 4 + table.len() * 8)
+            .sum::<usize>();
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    binary_weights::to_writer(weights, &mut writer, false)?;
+
+    writer.write_all(&(run.len() as u32).to_le_bytes())?;
+    writer.write_all(&run)?;
+
+    writer.write_all(&(tables.len() as u32).to_le_bytes())?;
+    for table in tables {
+        writer.write_all(&(table.len() as u32).to_le_bytes())?;
+    }
+    for table in tables {
+        for (net, absolute) in table {
+            writer.write_all(&net.to_le_bytes())?;
+            writer.write_all(&absolute.to_le_bytes())?;
+        }
+    }
+
+    writer.write_all(&(run_len as u64).to_le_bytes())?;
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Takes the next `len` bytes of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], WeightsError> {
+    if bytes.len() < len {
+        return Err(WeightsError::Format("truncated checkpoint".to_owned()));
+    }
+
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn read_array<A: Default + AsMut<[u8]>>(bytes: &[u8]) -> A {
+    let mut array = A::default();
+    array.as_mut().copy_from_slice(bytes);
+    array
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::training::{resume_td, CheckpointConfig};
+    use crate::v_function::{Legacy, LegacyWeights};
+    use crate::Engine;
+    use std::process;
+
+    fn config(num_games: u64) -> TrainingConfig {
+        TrainingConfig {
+            num_games,
+            alpha: 0.01,
+            lambda: 0.5,
+            temporal_coherence: true,
+            benchmark_interval: 1000,
+            seed: 7,
+            checkpoint: None,
+        }
+    }
+
+    /// Starts the runs after their first game, which skips the slow benchmark of game 0
+    fn state() -> TrainingState {
+        TrainingState {
+            game: 1,
+            ..TrainingState::new(7)
+        }
+    }
+
+    #[test]
+    fn resume_is_exact() {
+        let path = std::env::temp_dir().join(format!("swipy-checkpoint-{}.bin", process::id()));
+
+        let mut engine = Engine::<Legacy>::new(LegacyWeights::default());
+        let interrupted = TrainingConfig {
+            checkpoint: Some(CheckpointConfig {
+                path: path.clone(),
+                interval: 1,
+            }),
+            ..config(2)
+        };
+        resume_td(&mut engine, &interrupted, state(), |_| ()).unwrap();
+
+        let checkpoint = Checkpoint::<LegacyWeights>::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.state.game, 2);
+
+        let mut resumed = Engine::<Legacy>::new(checkpoint.weights);
+        resume_td(&mut resumed, &config(4), checkpoint.state, |_| ()).unwrap();
+
+        let mut uninterrupted = Engine::<Legacy>::new(LegacyWeights::default());
+        resume_td(&mut uninterrupted, &config(4), state(), |_| ()).unwrap();
+
+        assert_eq!(
+            resumed.into_weights().tables(),
+            uninterrupted.into_weights().tables()
+        );
+    }
+}
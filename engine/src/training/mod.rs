@@ -0,0 +1,483 @@
+use crate::engine::{Engine, SearchLimit};
+use crate::game::{Board, Direction, Game};
+use crate::testing::benchmark;
+use crate::v_function::{Coherence, VFunction, WeightsError};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+mod checkpoint;
+
+pub use self::checkpoint::Checkpoint;
+
+/// Traces whose weight falls below this are dropped from the eligibility trace
+const TRACE_CUTOFF: f32 = 0.01;
+/// The longest eligibility trace kept, which bounds the trace when λ is close to 1
+const MAX_TRACE_LEN: usize = 100;
+/// The number of games each thread plays in a batch, when training with several threads. The
+/// games of a batch don't see each other's updates, so smaller batches learn from fresher weights
+/// but leave the threads idle more often.
+const PARALLEL_BATCH_SIZE: u64 = 8;
+
+/// The parameters of a training run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainingConfig {
+    /// The number of training games
+    pub num_games: u64,
+    /// The learning rate
+    pub alpha: f32,
+    /// The trace decay, between 0 and 1. 0 gives TD(0), and higher values let an update flow
+    /// back to the afterstates that led to it.
+    pub lambda: f32,
+    /// Whether each weight adapts its own learning rate with Jaśkowski's delayed temporal
+    /// coherence, see `Coherence`, in which case `alpha` scales the adapted rates
+    pub temporal_coherence: bool,
+    /// The number of games between two benchmarks of the v-function
+    pub benchmark_interval: u64,
+    pub seed: u64,
+    /// Where and how often to save checkpoints. This isn't saved in the checkpoints themselves.
+    #[serde(skip)]
+    pub checkpoint: Option<CheckpointConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    pub path: PathBuf,
+    /// The number of games between two checkpoints. A checkpoint is also saved at the end of
+    /// the run.
+    pub interval: u64,
+}
+
+/// Everything needed to continue a training run exactly where it stopped
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainingState {
+    /// The number of games played so far
+    pub game: u64,
+    rng: XorShiftRng,
+    score_acc: f32,
+    /// Saved in binary by checkpoints, since it's as large as the weights
+    #[serde(skip)]
+    coherence: Coherence,
+    /// Every benchmark reported so far
+    pub history: Vec<TrainingProgress>,
+}
+
+impl TrainingState {
+    pub fn new(seed: u64) -> TrainingState {
+        TrainingState {
+            game: 0,
+            rng: XorShiftRng::seed_from_u64(seed),
+            score_acc: 0.,
+            coherence: Coherence::new(),
+            history: Vec::new(),
+        }
+    }
+}
+
+/// Trains the engine's v-function with TD(λ) afterstate learning, using truncated eligibility
+/// traces.
+///
+/// The training games are generated from `config.seed`, so two runs with the same seed and the
+/// same number of threads are identical. The periodic benchmarks always play the same games to
+/// make their scores comparable.
+///
+/// Fails only if a checkpoint can't be saved.
+pub fn train_td<F>(
+    engine: &mut Engine<impl VFunction>,
+    config: &TrainingConfig,
+    on_progress: F,
+) -> Result<(), WeightsError>
+where
+    F: Fn(TrainingProgress),
+{
+    resume_td(engine, config, TrainingState::new(config.seed), on_progress)
+}
+
+/// Continues a training run from its state, usually loaded from a `Checkpoint`. The engine must
+/// hold the weights saved along with the state.
+pub fn resume_td<F>(
+    engine: &mut Engine<impl VFunction>,
+    config: &TrainingConfig,
+    mut training: TrainingState,
+    on_progress: F,
+) -> Result<(), WeightsError>
+where
+    F: Fn(TrainingProgress),
+{
+    while training.game < config.num_games {
+        let i = training.game;
+
+        // Report training stats
+        if i % config.benchmark_interval == 0 {
+            let training_score = if i == 0 {
+                None
+            } else {
+                Some(training.score_acc / (config.benchmark_interval as f32))
+            };
+            let test_score =
+                benchmark(engine, 25, SearchLimit::Depth(3), config.seed, |_| ()).average;
+
+            let progress = TrainingProgress {
+                game: i,
+                training_score,
+                test_score,
+            };
+            training.history.push(progress.clone());
+            on_progress(progress);
+            training.score_acc = 0.;
+        }
+
+        if engine.threads() == 1 {
+            let seed = training.rng.gen();
+            let mut learner = EngineLearner {
+                engine,
+                config,
+                coherence: &mut training.coherence,
+            };
+
+            training.score_acc += play_training_game(&mut learner, seed, config.lambda);
+            training.coherence.end_episode();
+            training.game += 1;
+        } else {
+            // Batches never cross a benchmark or a checkpoint
+            let batch_size =
+                (engine.threads() as u64 * PARALLEL_BATCH_SIZE).min(next_stop(config, i) - i);
+
+            training.score_acc += train_batch(engine, config, &mut training, batch_size);
+            training.game += batch_size;
+        }
+
+        if let Some(checkpoint) = &config.checkpoint {
+            if training.game % checkpoint.interval == 0 || training.game == config.num_games {
+                checkpoint::save(&checkpoint.path, &engine.weights(), config, &training)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluates the afterstates of a training game and learns from their TD errors
+trait Learner {
+    fn eval(&self, afterstate: Board) -> f32;
+    fn learn(&mut self, afterstate: Board, error: f32);
+}
+
+/// Learns on the engine itself, one game after the other
+struct EngineLearner<'a, F: VFunction> {
+    engine: &'a mut Engine<F>,
+    config: &'a TrainingConfig,
+    coherence: &'a mut Coherence,
+}
+
+impl<F: VFunction> Learner for EngineLearner<'_, F> {
+    fn eval(&self, afterstate: Board) -> f32 {
+        self.engine.static_eval(afterstate)
+    }
+
+    fn learn(&mut self, afterstate: Board, error: f32) {
+        apply_update(self.engine, self.config, self.coherence, afterstate, error);
+    }
+}
+
+/// Evaluates with the engine's weights as they are, and logs the updates for later
+struct LoggingLearner<'a, F: VFunction> {
+    engine: &'a Engine<F>,
+    updates: Vec<(Board, f32)>,
+}
+
+impl<F: VFunction> Learner for LoggingLearner<'_, F> {
+    fn eval(&self, afterstate: Board) -> f32 {
+        self.engine.static_eval(afterstate)
+    }
+
+    fn learn(&mut self, afterstate: Board, error: f32) {
+        self.updates.push((afterstate, error));
+    }
+}
+
+/// Plays a training game, handing every afterstate to the learner along with its TD(λ) error as
+/// soon as the error is known. Returns the final score.
+fn play_training_game(learner: &mut impl Learner, seed: u64, lambda: f32) -> f32 {
+    let mut game = Game::new(seed);
+    let mut state = game.board();
+    let mut trace = EligibilityTrace::new(lambda);
+
+    while !state.is_dead() {
+        // Afterstate learning algorithm from Szubert and Jaśkowski
+        let action = greedy_move(learner, state);
+        let afterstate = state.move_candidate(action);
+        game.make_move(action);
+        let next_state = game.board();
+
+        let eval = learner.eval(afterstate);
+
+        let is_dead = next_state.is_dead();
+        let error = if is_dead {
+            -eval
+        } else {
+            let next_action = greedy_move(learner, next_state);
+            let next_afterstate = next_state.move_candidate(next_action);
+
+            // The tile-derived reward also counts the 4 that spawned on `next_state`, if any
+            let r = next_afterstate.score() - afterstate.score();
+            let next_eval = learner.eval(next_afterstate);
+
+            r + next_eval - eval
+        };
+
+        if let Some((board, error)) = trace.push(afterstate, error) {
+            learner.learn(board, error);
+        }
+
+        if is_dead {
+            break;
+        }
+
+        state = next_state;
+    }
+
+    for (board, error) in trace.drain() {
+        learner.learn(board, error);
+    }
+
+    game.score()
+}
+
+/// The move whose afterstate the learner values the most, which is the move of a depth 1 search
+fn greedy_move(learner: &impl Learner, state: Board) -> Direction {
+    state
+        .moves()
+        .map(|(direction, afterstate)| (direction, learner.eval(afterstate)))
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("values are not NAN"))
+        .expect("the game is not over")
+        .0
+}
+
+/// Plays a batch of training games in parallel, and returns their total score.
+///
+/// The engine's threads share its weights without changing them, logging the updates of every
+/// game instead. The logs are then learned one game after the other, in the order of the games,
+/// so the result doesn't depend on the timing of the threads.
+fn train_batch<F: VFunction>(
+    engine: &mut Engine<F>,
+    config: &TrainingConfig,
+    training: &mut TrainingState,
+    num_games: u64,
+) -> f32 {
+    let seeds: Vec<u64> = (0..num_games).map(|_| training.rng.gen()).collect();
+    let next_game = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(seeds.len()));
+
+    engine.for_each_thread(|engine| loop {
+        let i = next_game.fetch_add(1, Ordering::Relaxed);
+        let seed = match seeds.get(i) {
+            Some(&seed) => seed,
+            None => break,
+        };
+
+        let mut learner = LoggingLearner {
+            engine,
+            updates: Vec::new(),
+        };
+        let score = play_training_game(&mut learner, seed, config.lambda);
+
+        results.lock().unwrap().push((i, score, learner.updates));
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _, _)| *i);
+
+    let mut total_score = 0.;
+    for (_, score, updates) in results {
+        for (board, error) in updates {
+            apply_update(engine, config, &mut training.coherence, board, error);
+        }
+        training.coherence.end_episode();
+
+        total_score += score;
+    }
+
+    total_score
+}
+
+fn apply_update(
+    engine: &mut Engine<impl VFunction>,
+    config: &TrainingConfig,
+    coherence: &mut Coherence,
+    afterstate: Board,
+    error: f32,
+) {
+    let delta = config.alpha * error;
+
+    if config.temporal_coherence {
+        engine.learn_coherent(afterstate, delta, coherence);
+    } else {
+        engine.learn(afterstate, delta);
+    }
+}
+
+/// The first game after `game` at which the run stops for a benchmark, a checkpoint or its end
+fn next_stop(config: &TrainingConfig, game: u64) -> u64 {
+    let next_multiple = |interval: u64| (game / interval + 1) * interval;
+    let stop = config
+        .num_games
+        .min(next_multiple(config.benchmark_interval));
+
+    match &config.checkpoint {
+        Some(checkpoint) => stop.min(next_multiple(checkpoint.interval)),
+        None => stop,
+    }
+}
+
+/// The afterstates still waiting for their update, along with the sum of the TD errors seen
+/// since each of them, weighted by λ.
+///
+/// Once an afterstate is old enough for the next errors to weigh less than `TRACE_CUTOFF`, its
+/// update is released. With λ = 0 every update is released immediately, which is TD(0).
+struct EligibilityTrace {
+    lambda: f32,
+    len: usize,
+    /// The afterstates, their accumulated error and the weight of the next error
+    entries: VecDeque<(Board, f32, f32)>,
+}
+
+impl EligibilityTrace {
+    /// Panics if `lambda` isn't between 0 and 1, since the trace would then grow without bound
+    fn new(lambda: f32) -> EligibilityTrace {
+        assert!(
+            (0. ..=1.).contains(&lambda),
+            "λ must be between 0 and 1, not {}",
+            lambda
+        );
+
+        let len = if lambda <= 0. {
+            1
+        } else if lambda >= 1. {
+            MAX_TRACE_LEN
+        } else {
+            let len = (TRACE_CUTOFF.ln() / lambda.ln()).floor() as usize + 1;
+            len.min(MAX_TRACE_LEN)
+        };
+
+        EligibilityTrace {
+            lambda,
+            len,
+            entries: VecDeque::with_capacity(len),
+        }
+    }
+
+    /// Adds the TD error of a new afterstate, and returns the oldest afterstate and its error
+    /// if its update is complete
+    fn push(&mut self, afterstate: Board, error: f32) -> Option<(Board, f32)> {
+        self.entries.push_back((afterstate, 0., 1.));
+
+        for (_, acc, weight) in &mut self.entries {
+            *acc += *weight * error;
+            *weight *= self.lambda;
+        }
+
+        if self.entries.len() >= self.len {
+            self.entries.pop_front().map(|(board, acc, _)| (board, acc))
+        } else {
+            None
+        }
+    }
+
+    /// Releases the updates of the afterstates left at the end of a game
+    fn drain(&mut self) -> impl Iterator<Item = (Board, f32)> + '_ {
+        self.entries.drain(..).map(|(board, acc, _)| (board, acc))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainingProgress {
+    pub game: u64,
+    pub training_score: Option<f32>,
+    pub test_score: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::v_function::{Legacy, LegacyWeights, Weights};
+    use approx::*;
+
+    const BOARD_1: Board =
+        Board::from_array([[1, 2, 0, 0], [3, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+    const BOARD_2: Board =
+        Board::from_array([[1, 2, 0, 0], [3, 1, 0, 0], [0, 0, 0, 0], [0, 0, 1, 1]]);
+
+    #[test]
+    #[should_panic]
+    fn growing_trace() {
+        EligibilityTrace::new(1.5);
+    }
+
+    #[test]
+    fn td_0_trace() {
+        let mut trace = EligibilityTrace::new(0.);
+        let (board, error) = trace.push(BOARD_1, 2.).unwrap();
+
+        assert_eq!(board, BOARD_1);
+        assert_relative_eq!(error, 2.);
+        assert_eq!(trace.drain().count(), 0);
+    }
+
+    #[test]
+    fn td_lambda_trace() {
+        let mut trace = EligibilityTrace::new(0.5);
+        assert!(trace.push(BOARD_1, 2.).is_none());
+        assert!(trace.push(BOARD_2, 4.).is_none());
+
+        let updates: Vec<_> = trace.drain().collect();
+        assert_eq!(updates[0].0, BOARD_1);
+        assert_relative_eq!(updates[0].1, 2. + 0.5 * 4.);
+        assert_eq!(updates[1].0, BOARD_2);
+        assert_relative_eq!(updates[1].1, 4.);
+    }
+
+    #[test]
+    fn batches_ignore_the_threads() {
+        let config = TrainingConfig {
+            num_games: 6,
+            alpha: 0.01,
+            lambda: 0.5,
+            temporal_coherence: true,
+            benchmark_interval: 1000,
+            seed: 7,
+            checkpoint: None,
+        };
+
+        // A single thread plays the whole batch itself, on the same frozen weights
+        let train = |threads| {
+            let mut engine = Engine::<Legacy>::with_config(
+                LegacyWeights::default(),
+                EngineConfig {
+                    threads,
+                    ..EngineConfig::default()
+                },
+            );
+            let mut training = TrainingState::new(config.seed);
+
+            let score = train_batch(&mut engine, &config, &mut training, config.num_games);
+            (score, engine.into_weights())
+        };
+
+        let (score, weights) = train(1);
+        let (parallel_score, parallel_weights) = train(3);
+
+        assert_eq!(score, parallel_score);
+        assert_eq!(weights.tables(), parallel_weights.tables());
+        assert!(weights
+            .tables()
+            .into_iter()
+            .flatten()
+            .any(|&weight| weight != 0.));
+    }
+}
@@ -1,53 +1,249 @@
-use fnv::FnvHasher;
 use crate::game::Board;
+use fnv::FnvHasher;
+use serde_derive::Serialize;
 use std::hash::{Hash, Hasher};
-use std::iter::repeat;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Marks a slot's data as occupied, so that an empty slot never matches a board
+const OCCUPIED: u64 = 1 << 40;
+/// The low bits of the probability's mantissa that don't fit in a slot
+const PROB_DROPPED_BITS: u32 = 9;
 
 /// The result of a single position evaluation at a certain depth
 #[derive(Debug, Clone, Copy)]
 pub struct PositionEval {
     pub depth: u8,
     pub score: f32,
+    /// The probability of the path along which the position was searched, see
+    /// `EngineConfig::prob_cutoff`. The table rounds it up slightly.
+    pub prob: f32,
 }
 
 impl PositionEval {
-    pub fn new(depth: u8, score: f32) -> PositionEval {
-        PositionEval { depth, score }
+    pub fn new(depth: u8, score: f32, prob: f32) -> PositionEval {
+        PositionEval { depth, score, prob }
+    }
+
+    fn pack(self) -> u64 {
+        // Probabilities are positive, so their bits are ordered like them and rounding the bits
+        // up rounds the probability up
+        let dropped = (1 << PROB_DROPPED_BITS) - 1;
+        let prob = (self.prob.to_bits() + dropped) >> PROB_DROPPED_BITS;
+
+        (u64::from(prob) << 41)
+            | OCCUPIED
+            | (u64::from(self.depth) << 32)
+            | u64::from(self.score.to_bits())
+    }
+
+    fn unpack(data: u64) -> PositionEval {
+        PositionEval {
+            depth: (data >> 32) as u8,
+            score: f32::from_bits(data as u32),
+            prob: f32::from_bits(((data >> 41) as u32) << PROB_DROPPED_BITS),
+        }
     }
 }
 
-pub struct TranspositionTable(Vec<Option<(Board, PositionEval)>>);
+/// Decides which evaluation keeps a slot when a new board lands on an occupied slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    /// The newest evaluation always takes the slot
+    Always,
+    /// An evaluation only takes the slot of an evaluation that isn't deeper
+    DepthPreferred,
+    /// Every bucket has a depth-preferred slot and an always-replaced slot, so that deep
+    /// evaluations are kept without the table filling up with stale ones
+    TwoTier,
+}
+
+/// What the table did since its statistics were last reset
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TableStats {
+    /// The size of the table in bytes
+    pub bytes: usize,
+    /// The fraction of the slots holding an evaluation
+    pub fill_rate: f32,
+    pub hits: u64,
+    pub misses: u64,
+    /// The misses where the board's bucket was full of other boards
+    pub collisions: u64,
+}
+
+impl TableStats {
+    /// The fraction of the probes that found their board
+    pub fn hit_rate(&self) -> f32 {
+        let probes = self.hits + self.misses;
+        if probes == 0 {
+            0.
+        } else {
+            self.hits as f32 / probes as f32
+        }
+    }
+}
+
+/// A slot of the table. The key is stored xor-ed with the data, so that a slot torn by two
+/// threads writing at the same time reads as a miss instead of returning the wrong evaluation.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// A transposition table that can be shared between search threads without locking
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    replacement: Replacement,
+    /// The number of occupied slots, kept so that the statistics don't scan the table
+    filled: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    collisions: AtomicU64,
+}
 
 impl TranspositionTable {
-    pub fn new(size: usize) -> TranspositionTable {
-        TranspositionTable(repeat(None).take(size).collect())
+    /// Creates a table taking about `megabytes` of memory
+    pub fn new(megabytes: usize, replacement: Replacement) -> TranspositionTable {
+        let size = (megabytes << 20) / mem::size_of::<Slot>();
+
+        TranspositionTable::with_slots(size, replacement)
+    }
+
+    /// Creates a table with room for `size` evaluations, rounded to a whole number of buckets
+    pub fn with_slots(size: usize, replacement: Replacement) -> TranspositionTable {
+        let ways = Self::bucket_len(replacement);
+        let size = (size / ways).max(1) * ways;
+
+        TranspositionTable {
+            slots: (0..size).map(|_| Slot::default()).collect(),
+            replacement,
+            filled: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            collisions: AtomicU64::new(0),
+        }
     }
 
     pub fn get(&self, board: Board) -> Option<PositionEval> {
-        if let Some((entry_board, eval)) = self.0[self.bucket_of(board)] {
-            if entry_board == board {
-                return Some(eval);
+        let bucket = self.bucket_of(board);
+        let mut full = true;
+
+        for i in bucket {
+            let (key, data) = self.load(i);
+
+            if data & OCCUPIED == 0 {
+                full = false;
+            } else if key == board.into_u64() {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(PositionEval::unpack(data));
             }
         }
 
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if full {
+            self.collisions.fetch_add(1, Ordering::Relaxed);
+        }
+
         None
     }
 
-    pub fn set(&mut self, board: Board, eval: PositionEval) {
-        let i = self.bucket_of(board);
+    pub fn set(&self, board: Board, eval: PositionEval) {
+        let bucket = self.bucket_of(board);
+
+        // A board already in the bucket is updated in place, if the new evaluation is deeper
+        for i in bucket.clone() {
+            let (key, data) = self.load(i);
 
-        if let Some((entry_board, entry_eval)) = self.0[i] {
-            if entry_board == board && entry_eval.depth <= eval.depth {
-                self.0[i] = Some((board, eval));
+            if data & OCCUPIED != 0 && key == board.into_u64() {
+                if PositionEval::unpack(data).depth <= eval.depth {
+                    self.store(i, board, eval);
+                }
+                return;
             }
-        } else {
-            self.0[i] = Some((board, eval));
+        }
+
+        let slot = match self.replacement {
+            Replacement::Always => Some(bucket.start),
+            Replacement::DepthPreferred => {
+                Some(bucket.start).filter(|&i| self.is_replaceable(i, eval))
+            }
+            Replacement::TwoTier => {
+                if self.is_replaceable(bucket.start, eval) {
+                    // The evaluation it replaces, if any, is demoted to the always-replaced slot
+                    let (key, data) = self.load(bucket.start);
+                    if data & OCCUPIED != 0 {
+                        self.store_raw(bucket.start + 1, key, data);
+                    }
+                    Some(bucket.start)
+                } else {
+                    Some(bucket.start + 1)
+                }
+            }
+        };
+
+        if let Some(i) = slot {
+            self.store(i, board, eval);
         }
     }
 
+    /// The statistics of the table since the last reset
+    pub fn stats(&self) -> TableStats {
+        let filled = self.filled.load(Ordering::Relaxed);
+
+        TableStats {
+            bytes: self.slots.len() * mem::size_of::<Slot>(),
+            fill_rate: filled as f32 / self.slots.len() as f32,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            collisions: self.collisions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The number of probes that found their board since the last reset
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_stats(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.collisions.store(0, Ordering::Relaxed);
+    }
+
+    /// Empties every slot, which takes time proportional to the size of the table
     pub fn clear(&mut self) {
-        for i in 0..self.0.len() {
-            self.0[i] = None;
+        for slot in &mut self.slots {
+            *slot = Slot::default();
+        }
+        self.filled.store(0, Ordering::Relaxed);
+        self.reset_stats();
+    }
+
+    /// Whether `eval` may take the slot of the evaluation of another board
+    fn is_replaceable(&self, i: usize, eval: PositionEval) -> bool {
+        let (_, data) = self.load(i);
+        data & OCCUPIED == 0 || PositionEval::unpack(data).depth <= eval.depth
+    }
+
+    /// Reads the key and the data of a slot
+    fn load(&self, i: usize) -> (u64, u64) {
+        let data = self.slots[i].data.load(Ordering::Relaxed);
+        let key = self.slots[i].key.load(Ordering::Relaxed) ^ data;
+        (key, data)
+    }
+
+    fn store(&self, i: usize, board: Board, eval: PositionEval) {
+        self.store_raw(i, board.into_u64(), eval.pack());
+    }
+
+    /// Writes occupied data to a slot, counting the slot if it was empty
+    fn store_raw(&self, i: usize, key: u64, data: u64) {
+        self.slots[i].key.store(key ^ data, Ordering::Relaxed);
+        let previous = self.slots[i].data.swap(data, Ordering::Relaxed);
+
+        if previous & OCCUPIED == 0 {
+            self.filled.fetch_add(1, Ordering::Relaxed);
         }
     }
 
@@ -57,7 +253,80 @@ impl TranspositionTable {
         hasher.finish()
     }
 
-    fn bucket_of(&self, board: Board) -> usize {
-        (Self::hash(board) as usize) % self.0.len()
+    fn bucket_len(replacement: Replacement) -> usize {
+        match replacement {
+            Replacement::TwoTier => 2,
+            Replacement::Always | Replacement::DepthPreferred => 1,
+        }
+    }
+
+    /// The range of slots where `board` can be stored
+    fn bucket_of(&self, board: Board) -> std::ops::Range<usize> {
+        let len = Self::bucket_len(self.replacement);
+        let start = (Self::hash(board) as usize) % (self.slots.len() / len) * len;
+        start..start + len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARDS: [Board; 3] = [
+        Board::from_array([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]),
+        Board::from_array([[2, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]),
+        Board::from_array([[3, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]),
+    ];
+
+    /// Stores a deep evaluation then two shallow ones in a table with a single bucket
+    fn fill(replacement: Replacement) -> TranspositionTable {
+        let table = TranspositionTable::with_slots(1, replacement);
+        table.set(BOARDS[0], PositionEval::new(5, 1., 1.));
+        table.set(BOARDS[1], PositionEval::new(2, 2., 1.));
+        table.set(BOARDS[2], PositionEval::new(2, 3., 1.));
+        table
+    }
+
+    #[test]
+    fn always_replace() {
+        let table = fill(Replacement::Always);
+        assert!(table.get(BOARDS[0]).is_none());
+        assert!(table.get(BOARDS[2]).is_some());
+    }
+
+    #[test]
+    fn depth_preferred() {
+        let table = fill(Replacement::DepthPreferred);
+        assert!(table.get(BOARDS[0]).is_some());
+        assert!(table.get(BOARDS[2]).is_none());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn two_tier() {
+        let table = fill(Replacement::TwoTier);
+        assert!(table.get(BOARDS[0]).is_some());
+        assert!(table.get(BOARDS[1]).is_none());
+        assert!(table.get(BOARDS[2]).is_some());
+
+        let stats = table.stats();
+        assert_eq!((stats.hits, stats.misses, stats.collisions), (2, 1, 1));
+        assert_eq!(stats.fill_rate, 1.);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn fill_rate() {
+        let mut table = TranspositionTable::with_slots(2, Replacement::TwoTier);
+        table.set(BOARDS[0], PositionEval::new(2, 1., 1.));
+        table.set(BOARDS[0], PositionEval::new(3, 1., 1.));
+        assert_eq!(table.stats().fill_rate, 0.5);
+
+        // The deeper evaluation demotes the first one to the other slot
+        table.set(BOARDS[1], PositionEval::new(4, 1., 1.));
+        assert_eq!(table.stats().fill_rate, 1.);
+
+        table.clear();
+        assert_eq!(table.stats().fill_rate, 0.);
     }
 }
@@ -0,0 +1,230 @@
+//! A compact binary format for weights files.
+//!
+//! All the integers and floats are little-endian. A file is laid out as follows:
+//!
+//! | Field       | Encoding                                                    |
+//! |-------------|-------------------------------------------------------------|
+//! | magic       | the 4 bytes `SWPW`                                          |
+//! | version     | `u16`                                                       |
+//! | flags       | `u16`, the lowest bit is set when the body is compressed    |
+//! | v-function  | `u16` length followed by the UTF-8 name of the v-function   |
+//! | body        | the rest of the file, deflated with zlib when compressed    |
+//!
+//! The body holds the layout and the weight tables:
+//!
+//! | Field       | Encoding                                                    |
+//! |-------------|-------------------------------------------------------------|
+//! | layout      | `u32` length followed by the JSON layout of the weights     |
+//! | shape       | `u32` number of tables, followed by the `u32` size of each  |
+//! | data        | every table, one after the other, as `f32`s                 |
+
+use super::weights_file::WeightsError;
+use super::Weights;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"SWPW";
+const VERSION: u16 = 1;
+const FLAG_COMPRESSED: u16 = 1;
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Reads the name of the v-function from the header of a binary weights file
+pub fn read_v_function(bytes: &[u8]) -> Result<String, WeightsError> {
+    read_header(&mut Reader(bytes)).map(|(_, v_function)| v_function)
+}
+
+pub fn from_slice<W: Weights>(bytes: &[u8]) -> Result<W, WeightsError> {
+    let mut reader = Reader(bytes);
+    let (flags, v_function) = read_header(&mut reader)?;
+
+    if v_function != W::name() {
+        return Err(WeightsError::WrongVFunction {
+            expected: W::name(),
+            found: v_function,
+        });
+    }
+
+    if flags & FLAG_COMPRESSED != 0 {
+        let mut body = Vec::new();
+        ZlibDecoder::new(reader.0).read_to_end(&mut body)?;
+        read_body(&mut Reader(&body))
+    } else {
+        read_body(&mut reader)
+    }
+}
+
+pub fn to_writer<W: Weights>(
+    weights: &W,
+    mut writer: impl Write,
+    compressed: bool,
+) -> Result<(), WeightsError> {
+    let name = W::name();
+    let flags = if compressed { FLAG_COMPRESSED } else { 0 };
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&flags.to_le_bytes())?;
+    writer.write_all(&(name.len() as u16).to_le_bytes())?;
+    writer.write_all(name.as_bytes())?;
+
+    if compressed {
+        let mut encoder = ZlibEncoder::new(writer, Compression::default());
+        write_body(weights, &mut encoder)?;
+        encoder.finish()?;
+    } else {
+        write_body(weights, writer)?;
+    }
+
+    Ok(())
+}
+
+fn read_header(reader: &mut Reader) -> Result<(u16, String), WeightsError> {
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(WeightsError::Format("not a binary weights file".to_owned()));
+    }
+
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(WeightsError::Format(format!(
+            "unsupported binary weights version {}",
+            version
+        )));
+    }
+
+    let flags = reader.u16()?;
+    let name_len = reader.u16()? as usize;
+    let v_function = String::from_utf8(reader.take(name_len)?.to_vec())
+        .map_err(|_| WeightsError::Format("invalid v-function name".to_owned()))?;
+
+    Ok((flags, v_function))
+}
+
+fn read_body<W: Weights>(reader: &mut Reader) -> Result<W, WeightsError> {
+    let layout_len = reader.u32()? as usize;
+    let layout = serde_json::from_slice(reader.take(layout_len)?)?;
+
+    // Reading the sizes before allocating anything bounds the allocations by the file's length
+    let num_tables = reader.u32()? as usize;
+    let shape: Vec<usize> = reader
+        .take(num_tables * 4)?
+        .chunks(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        .collect();
+
+    let mut tables = Vec::with_capacity(num_tables);
+    for size in shape {
+        let data = reader.take(size * 4)?;
+        let table = data
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        tables.push(table);
+    }
+
+    W::from_tables(layout, tables)
+        .ok_or_else(|| WeightsError::Format("the shape of the weights doesn't match".to_owned()))
+}
+
+fn write_body<W: Weights>(weights: &W, mut writer: impl Write) -> Result<(), WeightsError> {
+    let layout = serde_json::to_vec(&weights.layout())?;
+    writer.write_all(&(layout.len() as u32).to_le_bytes())?;
+    writer.write_all(&layout)?;
+
+    let tables = weights.tables();
+    writer.write_all(&(tables.len() as u32).to_le_bytes())?;
+    for table in &tables {
+        writer.write_all(&(table.len() as u32).to_le_bytes())?;
+    }
+
+    for table in &tables {
+        for weight in table.iter() {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A cursor over the bytes of a file that fails on truncated input
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WeightsError> {
+        if self.0.len() < len {
+            return Err(WeightsError::Format("truncated weights file".to_owned()));
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, WeightsError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WeightsError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{LegacyWeights, NTupleSmallWeights};
+
+    #[test]
+    fn round_trip() {
+        for compressed in &[false, true] {
+            let weights = NTupleSmallWeights::optimized().unwrap();
+            let mut bytes = Vec::new();
+            to_writer(&weights, &mut bytes, *compressed).unwrap();
+
+            assert!(is_binary(&bytes));
+            let loaded: NTupleSmallWeights = from_slice(&bytes).unwrap();
+            assert_eq!(loaded.tables(), weights.tables());
+        }
+    }
+
+    #[test]
+    fn wrong_v_function() {
+        let mut bytes = Vec::new();
+        to_writer(&LegacyWeights::optimized().unwrap(), &mut bytes, false).unwrap();
+
+        assert_eq!(read_v_function(&bytes).unwrap(), "legacy");
+        assert!(from_slice::<NTupleSmallWeights>(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = Vec::new();
+        to_writer(&LegacyWeights::optimized().unwrap(), &mut bytes, false).unwrap();
+        bytes.pop();
+
+        assert!(from_slice::<LegacyWeights>(&bytes).is_err());
+    }
+
+    #[test]
+    fn corrupt_table_count() {
+        let mut bytes = Vec::new();
+        to_writer(&LegacyWeights::optimized().unwrap(), &mut bytes, false).unwrap();
+
+        // Overwrites the number of tables, which follows the header and the layout
+        let name = LegacyWeights::name();
+        let layout_len = serde_json::to_vec(&LegacyWeights::optimized().unwrap().layout())
+            .unwrap()
+            .len();
+        let offset = MAGIC.len() + 6 + name.len() + 4 + layout_len;
+        bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        match from_slice::<LegacyWeights>(&bytes) {
+            Err(WeightsError::Format(_)) => {}
+            result => panic!("expected a format error, got {:?}", result.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use super::VFunction;
+use crate::game::Board;
+use fnv::FnvHashMap;
+use serde_derive::{Deserialize, Serialize};
+
+/// The per-weight state of Delayed Temporal Coherence learning, from Jaśkowski's "Mastering
+/// 2048 with Delayed Temporal Coherence Learning", which builds on Beal and Smith's temporal
+/// coherence.
+///
+/// Every weight keeps the net sum and the absolute sum of the updates it was asked to make. The
+/// ratio of the two is the weight's own learning rate: it stays high while the updates agree,
+/// and shrinks once they start cancelling each other out.
+///
+/// The weights move as soon as an update is learned, but the rates don't: the updates of an
+/// episode are accumulated, and only added to the sums by `end_episode`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Coherence {
+    /// The net and absolute sums of the updates of each weight, addressed like in
+    /// `VFunction::for_each_feature`
+    tables: Vec<Vec<(f32, f32)>>,
+    /// The sum of the updates of each weight during the current episode. Checkpoints are saved
+    /// between episodes, when it's empty.
+    #[serde(skip)]
+    pending: FnvHashMap<(usize, usize), f32>,
+    /// The features of the board being learned, kept to avoid an allocation per update
+    #[serde(skip)]
+    features: Vec<(usize, usize)>,
+}
+
+impl Coherence {
+    pub fn new() -> Coherence {
+        Coherence::default()
+    }
+
+    /// The learning rate of a weight, which is 1 until the weight is first updated
+    pub fn rate(&self, table: usize, index: usize) -> f32 {
+        match self.tables.get(table).and_then(|table| table.get(index)) {
+            Some(&(net, absolute)) if absolute > 0. => net.abs() / absolute,
+            _ => 1.,
+        }
+    }
+
+    /// Moves every weight used to evaluate `state` by its share of `delta`, scaled by its
+    /// learning rate. The rates don't change until `end_episode`.
+    pub fn learn<F: VFunction>(&mut self, v_function: &mut F, state: Board, delta: f32) {
+        let mut features = std::mem::take(&mut self.features);
+        features.clear();
+        v_function.for_each_feature(state, |table, index| features.push((table, index)));
+
+        let adjusted_delta = delta / (features.len() as f32);
+
+        for &(table, index) in &features {
+            *v_function.weight_mut(table, index) += self.rate(table, index) * adjusted_delta;
+            *self.pending.entry((table, index)).or_insert(0.) += adjusted_delta;
+        }
+
+        self.features = features;
+    }
+
+    /// Updates the learning rates with the updates accumulated since the last episode ended
+    pub fn end_episode(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        for ((table, index), delta) in pending {
+            self.record(table, index, delta);
+        }
+    }
+
+    /// The net and absolute sums of the updates of each weight, which checkpoints save
+    pub(crate) fn tables(&self) -> &[Vec<(f32, f32)>] {
+        &self.tables
+    }
+
+    pub(crate) fn from_tables(tables: Vec<Vec<(f32, f32)>>) -> Coherence {
+        Coherence {
+            tables,
+            pending: FnvHashMap::default(),
+            features: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, table: usize, index: usize, delta: f32) {
+        if self.tables.len() <= table {
+            self.tables.resize(table + 1, Vec::new());
+        }
+
+        let table = &mut self.tables[table];
+        if table.len() <= index {
+            table.resize(index + 1, (0., 0.));
+        }
+
+        table[index].0 += delta;
+        table[index].1 += delta.abs();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{Legacy, NTupleSmall, Weights};
+    use approx::*;
+
+    const BOARD_1: Board =
+        Board::from_array([[1, 2, 0, 0], [3, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+
+    /// Sums the weights given by `for_each_feature`, which must match `eval`
+    fn feature_eval<F: VFunction>(v_function: &F, state: Board) -> f32 {
+        let mut features = Vec::new();
+        v_function.for_each_feature(state, |table, index| features.push((table, index)));
+
+        let mut v_function = v_function.clone();
+        features
+            .into_iter()
+            .map(|(table, index)| *v_function.weight_mut(table, index))
+            .sum()
+    }
+
+    #[test]
+    fn features_match_eval() {
+        let legacy = Legacy::new(Weights::optimized().unwrap());
+        assert_relative_eq!(feature_eval(&legacy, BOARD_1), legacy.eval(BOARD_1));
+
+        let n_tuple_small = NTupleSmall::new(Weights::optimized().unwrap());
+        assert_relative_eq!(
+            feature_eval(&n_tuple_small, BOARD_1),
+            n_tuple_small.eval(BOARD_1)
+        );
+    }
+
+    #[test]
+    fn first_update_is_plain() {
+        let mut coherence = Coherence::new();
+        let mut v_function = Legacy::default();
+        coherence.learn(&mut v_function, BOARD_1, 1.0);
+
+        let mut plain = Legacy::default();
+        plain.learn(BOARD_1, 1.0);
+        assert_relative_eq!(v_function.eval(BOARD_1), plain.eval(BOARD_1));
+    }
+
+    #[test]
+    fn incoherent_updates_slow_down() {
+        let mut coherence = Coherence::new();
+        let mut v_function = Legacy::default();
+        coherence.learn(&mut v_function, BOARD_1, 1.0);
+        coherence.end_episode();
+        coherence.learn(&mut v_function, BOARD_1, -1.0);
+
+        // The contradicting update only slows the weight down once its episode ends
+        assert_relative_eq!(coherence.rate(0, 0), 1.);
+        assert_relative_eq!(v_function.eval(BOARD_1), 0.);
+
+        coherence.end_episode();
+        assert!(coherence.rate(0, 0) < 1.);
+    }
+}
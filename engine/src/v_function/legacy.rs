@@ -1,11 +1,11 @@
-use super::{VFunction, Weights};
+use super::{weights_file, VFunction, Weights};
 use crate::game::Board;
 use lazy_static::lazy_static;
 use serde_derive::{Deserialize, Serialize};
 
 lazy_static! {
     static ref OPTIMIZED: LegacyWeights =
-        serde_json::from_slice(include_bytes!("../../../networks/legacy.json"))
+        weights_file::from_slice(include_bytes!("../../../networks/legacy.json"))
             .expect("valid legacy weights");
 }
 
@@ -16,8 +16,28 @@ pub struct LegacyWeights {
 }
 
 impl Weights for LegacyWeights {
-    fn optimized() -> LegacyWeights {
-        OPTIMIZED.clone()
+    fn name() -> String {
+        "legacy".to_owned()
+    }
+
+    fn optimized() -> Option<LegacyWeights> {
+        Some(OPTIMIZED.clone())
+    }
+
+    fn tables(&self) -> Vec<&[f32]> {
+        vec![&self.outer_pos_bonus, &self.inner_pos_bonus]
+    }
+
+    fn from_tables(_layout: serde_json::Value, tables: Vec<Vec<f32>>) -> Option<LegacyWeights> {
+        match tables.as_slice() {
+            [outer, inner] if outer.len() == 16 && inner.len() == 16 => {
+                let mut weights = LegacyWeights::default();
+                weights.outer_pos_bonus.copy_from_slice(outer);
+                weights.inner_pos_bonus.copy_from_slice(inner);
+                Some(weights)
+            }
+            _ => None,
+        }
     }
 }
 
@@ -74,4 +94,22 @@ impl VFunction for Legacy {
     fn into_weights(self) -> LegacyWeights {
         self.weights
     }
+
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, mut f: G) {
+        for i in 0..4 {
+            for line in &[state.row_at(i), state.column_at(i)] {
+                f(0, line.tile_at(0) as usize);
+                f(1, line.tile_at(1) as usize);
+                f(1, line.tile_at(2) as usize);
+                f(0, line.tile_at(3) as usize);
+            }
+        }
+    }
+
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32 {
+        match table {
+            0 => &mut self.weights.outer_pos_bonus[index],
+            _ => &mut self.weights.inner_pos_bonus[index],
+        }
+    }
 }
@@ -1,23 +1,92 @@
+//! The v-functions that statically evaluate boards for the engine, and their weights.
+//!
+//! Only `Legacy` and `NTupleSmall` ship with trained weights. The other v-functions start from
+//! blank weights, so they have to be trained and their weights loaded with `Weights::load`.
+
 use crate::game::Board;
 use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::Debug;
+use std::io::Write;
+use std::path::Path;
 
+pub(crate) mod binary_weights;
+mod coherence;
 mod legacy;
+mod multi_stage;
 mod n_tuple_medium;
+mod n_tuple_network;
 mod n_tuple_small;
+pub(crate) mod weights_file;
 
+pub use self::coherence::Coherence;
 pub use self::legacy::*;
+pub use self::multi_stage::*;
 pub use self::n_tuple_medium::*;
+pub use self::n_tuple_network::*;
 pub use self::n_tuple_small::*;
+pub use self::weights_file::{read_v_function, WeightsError, WeightsFormat};
 
 pub trait Weights: Serialize + DeserializeOwned {
-    fn optimized() -> Self;
+    /// The name of the v-function using these weights, which is written in weights files
+    fn name() -> String;
+
+    /// The trained weights shipped with the crate, if the v-function has any
+    fn optimized() -> Option<Self> {
+        None
+    }
+
+    /// The weight tables, in a fixed order. Used by the binary weights format.
+    fn tables(&self) -> Vec<&[f32]>;
+
+    /// Rebuilds the weights from their layout and tables, or returns `None` if the tables don't
+    /// have the right shape
+    fn from_tables(layout: serde_json::Value, tables: Vec<Vec<f32>>) -> Option<Self>;
+
+    /// Describes the shape of weights whose tables can vary in size
+    fn layout(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// Checks weights read from a file before they're used
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Loads weights from a file, checking that they belong to the right v-function
+    fn load(path: impl AsRef<Path>) -> Result<Self, WeightsError> {
+        weights_file::load(path.as_ref())
+    }
+
+    /// Saves the weights to a JSON file
+    fn save(&self, path: impl AsRef<Path>) -> Result<(), WeightsError> {
+        self.save_as(path, WeightsFormat::Json)
+    }
+
+    fn save_as(&self, path: impl AsRef<Path>, format: WeightsFormat) -> Result<(), WeightsError> {
+        weights_file::save(self, path.as_ref(), format)
+    }
+
+    /// Writes the weights in the format of weights files
+    fn write_to(&self, writer: impl Write) -> Result<(), WeightsError> {
+        weights_file::to_writer(self, writer)
+    }
 }
 
-pub trait VFunction: Debug {
+pub trait VFunction: Debug + Clone + Send + Sync {
     type Weights: Weights + Debug + Clone + Default;
     fn new(weights: Self::Weights) -> Self;
     fn eval(&self, state: Board) -> f32;
     fn learn(&mut self, state: Board, delta: f32);
     fn into_weights(self) -> Self::Weights;
+
+    /// Calls `f` with the table and the index of every weight summed by `eval`
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, f: G);
+
+    /// The weight at an address given by `for_each_feature`
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32;
+
+    /// Like `learn`, but with the learning rate of each weight adapted by `coherence`
+    fn learn_coherent(&mut self, state: Board, delta: f32, coherence: &mut Coherence) {
+        coherence.learn(self, state, delta)
+    }
 }
@@ -0,0 +1,290 @@
+use super::{Coherence, VFunction, Weights};
+use crate::game::Board;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The stage boundaries used by default: one stage before the 8192 tile, one before the 16384
+/// tile, and one after.
+const DEFAULT_BOUNDARIES: [u64; 2] = [13, 14];
+
+/// The weights of a multi-stage v-function, with one set of weights per game phase
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultiStageWeights<W> {
+    /// The tiles, as exponents, that start each stage after the first one. A board is in the
+    /// stage of the highest boundary reached by its highest tile.
+    pub boundaries: Vec<u64>,
+    /// Whether a stage seen for the first time starts from a copy of the previous stage's
+    /// weights instead of blank weights
+    pub promotion: bool,
+    /// The weights of each stage, or `None` for the stages that were never trained
+    pub stages: Vec<Option<W>>,
+}
+
+impl<W> MultiStageWeights<W> {
+    /// The stage of a board
+    pub fn stage_of(&self, board: Board) -> usize {
+        stage_of(&self.boundaries, board)
+    }
+}
+
+impl<W: Default> Default for MultiStageWeights<W> {
+    fn default() -> Self {
+        let mut stages = vec![Some(W::default())];
+        stages.extend(DEFAULT_BOUNDARIES.iter().map(|_| None));
+
+        MultiStageWeights {
+            boundaries: DEFAULT_BOUNDARIES.to_vec(),
+            promotion: true,
+            stages,
+        }
+    }
+}
+
+impl<W: Weights + Default> Weights for MultiStageWeights<W> {
+    fn name() -> String {
+        format!("multi_stage_{}", W::name())
+    }
+
+    fn tables(&self) -> Vec<&[f32]> {
+        self.stages
+            .iter()
+            .flatten()
+            .flat_map(|stage| stage.tables())
+            .collect()
+    }
+
+    fn from_tables(layout: serde_json::Value, tables: Vec<Vec<f32>>) -> Option<Self> {
+        let boundaries = serde_json::from_value(layout.get("boundaries")?.clone()).ok()?;
+        let promotion = layout.get("promotion")?.as_bool()?;
+        let mut tables = tables.into_iter();
+
+        // Each trained stage is described by its own layout and its number of tables
+        let stages = layout
+            .get("stages")?
+            .as_array()?
+            .iter()
+            .map(|stage| {
+                if stage.is_null() {
+                    return Some(None);
+                }
+
+                let num_tables = stage.get("num_tables")?.as_u64()? as usize;
+                let stage_tables = tables.by_ref().take(num_tables).collect();
+                W::from_tables(stage.get("layout")?.clone(), stage_tables).map(Some)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(MultiStageWeights {
+            boundaries,
+            promotion,
+            stages,
+        })
+    }
+
+    fn layout(&self) -> serde_json::Value {
+        let stages: Vec<serde_json::Value> = self
+            .stages
+            .iter()
+            .map(|stage| match stage {
+                Some(stage) => json!({
+                    "layout": stage.layout(),
+                    "num_tables": stage.tables().len(),
+                }),
+                None => serde_json::Value::Null,
+            })
+            .collect();
+
+        json!({
+            "boundaries": self.boundaries,
+            "promotion": self.promotion,
+            "stages": stages,
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("the stage boundaries must be increasing".to_owned());
+        }
+
+        if self.stages.len() != self.boundaries.len() + 1 {
+            return Err("there must be one more stage than stage boundaries".to_owned());
+        }
+
+        self.stages.iter().flatten().try_for_each(W::validate)
+    }
+}
+
+/// A v-function split in game phases, each phase being evaluated by its own copy of `F`.
+///
+/// Each stage only learns from the boards in that stage. Until a stage is trained, it's
+/// evaluated with the weights of the closest trained stage before it.
+#[derive(Debug, Clone)]
+pub struct MultiStage<F> {
+    boundaries: Vec<u64>,
+    promotion: bool,
+    stages: Vec<Option<F>>,
+}
+
+impl<F: VFunction> MultiStage<F> {
+    /// The closest trained stage at or before `stage`
+    fn trained_stage(&self, stage: usize) -> &F {
+        self.stages[..=stage]
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .expect("the first stage is always trained")
+    }
+
+    /// The v-function of a stage, which is created the first time the stage is trained
+    fn stage_mut(&mut self, stage: usize) -> &mut F {
+        if self.stages[stage].is_none() {
+            // Weight promotion
+            let new_stage = if self.promotion {
+                self.trained_stage(stage).clone()
+            } else {
+                F::new(Default::default())
+            };
+
+            self.stages[stage] = Some(new_stage);
+        }
+
+        self.stages[stage].as_mut().unwrap()
+    }
+}
+
+impl<F: VFunction> Default for MultiStage<F> {
+    fn default() -> Self {
+        MultiStage::new(MultiStageWeights::default())
+    }
+}
+
+impl<F: VFunction> VFunction for MultiStage<F> {
+    type Weights = MultiStageWeights<F::Weights>;
+
+    fn new(weights: MultiStageWeights<F::Weights>) -> Self {
+        let mut stages: Vec<Option<F>> = weights
+            .stages
+            .into_iter()
+            .map(|stage| stage.map(F::new))
+            .collect();
+
+        if stages[0].is_none() {
+            stages[0] = Some(F::new(Default::default()));
+        }
+
+        MultiStage {
+            boundaries: weights.boundaries,
+            promotion: weights.promotion,
+            stages,
+        }
+    }
+
+    fn eval(&self, state: Board) -> f32 {
+        self.trained_stage(stage_of(&self.boundaries, state))
+            .eval(state)
+    }
+
+    fn learn(&mut self, state: Board, delta: f32) {
+        let stage = stage_of(&self.boundaries, state);
+        self.stage_mut(stage).learn(state, delta);
+    }
+
+    fn into_weights(self) -> MultiStageWeights<F::Weights> {
+        MultiStageWeights {
+            boundaries: self.boundaries,
+            promotion: self.promotion,
+            stages: self
+                .stages
+                .into_iter()
+                .map(|stage| stage.map(F::into_weights))
+                .collect(),
+        }
+    }
+
+    /// The tables of each stage are numbered after the tables of `F`, interleaved by stage
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, mut f: G) {
+        let stage = stage_of(&self.boundaries, state);
+        let num_stages = self.stages.len();
+
+        self.trained_stage(stage)
+            .for_each_feature(state, |table, index| f(table * num_stages + stage, index));
+    }
+
+    /// Panics if the weight belongs to a stage that was never trained
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32 {
+        let num_stages = self.stages.len();
+
+        self.stages[table % num_stages]
+            .as_mut()
+            .expect("the stage is trained")
+            .weight_mut(table / num_stages, index)
+    }
+
+    fn learn_coherent(&mut self, state: Board, delta: f32, coherence: &mut Coherence) {
+        // Makes sure the weights of the board's stage exist before updating them
+        self.stage_mut(stage_of(&self.boundaries, state));
+        coherence.learn(self, state, delta);
+    }
+}
+
+fn stage_of(boundaries: &[u64], board: Board) -> usize {
+    let highest_tile = board.highest_tile();
+
+    boundaries
+        .iter()
+        .take_while(|&&boundary| highest_tile >= boundary)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{Legacy, LegacyWeights};
+    use approx::*;
+
+    /// A board in the first stage
+    const BOARD_1: Board =
+        Board::from_array([[1, 2, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+    /// A board in the second stage
+    const BOARD_2: Board =
+        Board::from_array([[1, 2, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 13]]);
+
+    fn weights(promotion: bool) -> MultiStageWeights<LegacyWeights> {
+        MultiStageWeights {
+            promotion,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stage_of() {
+        let weights = weights(true);
+        assert_eq!(weights.stage_of(BOARD_1), 0);
+        assert_eq!(weights.stage_of(BOARD_2), 1);
+    }
+
+    #[test]
+    fn stages_learn_separately() {
+        let mut v_function = MultiStage::<Legacy>::new(weights(false));
+        v_function.learn(BOARD_2, 1.0);
+
+        assert_relative_eq!(v_function.eval(BOARD_1), 0.);
+        assert!(v_function.eval(BOARD_2) > 0.);
+    }
+
+    #[test]
+    fn promotion() {
+        let mut v_function = MultiStage::<Legacy>::new(weights(true));
+        v_function.learn(BOARD_1, 1.0);
+        let promoted_eval = v_function.eval(BOARD_2);
+        v_function.learn(BOARD_2, 1.0);
+
+        let weights = v_function.into_weights();
+        assert!(weights.stages[1].is_some());
+        assert!(weights.stages[2].is_none());
+
+        let stage_1 = Legacy::new(weights.stages[1].clone().unwrap());
+        assert!(stage_1.eval(BOARD_2) > promoted_eval);
+    }
+}
@@ -1,14 +1,7 @@
 use super::{VFunction, Weights};
 use crate::game::Board;
-use lazy_static::lazy_static;
 use serde_derive::{Deserialize, Serialize};
 
-lazy_static! {
-    static ref OPTIMIZED: NTupleMediumWeights =
-        serde_json::from_slice(include_bytes!("../../../networks/n_tuple_small.json"))
-            .expect("valid legacy weights");
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NTupleMediumWeights {
     pub outer: Vec<f32>,
@@ -25,8 +18,22 @@ impl Default for NTupleMediumWeights {
 }
 
 impl Weights for NTupleMediumWeights {
-    fn optimized() -> Self {
-        OPTIMIZED.clone()
+    fn name() -> String {
+        "n_tuple_medium".to_owned()
+    }
+
+    fn tables(&self) -> Vec<&[f32]> {
+        vec![&self.outer, &self.inner]
+    }
+
+    fn from_tables(_layout: serde_json::Value, mut tables: Vec<Vec<f32>>) -> Option<Self> {
+        if tables.len() != 2 || tables.iter().any(|table| table.len() != 0xF_0000) {
+            return None;
+        }
+
+        let inner = tables.pop()?;
+        let outer = tables.pop()?;
+        Some(NTupleMediumWeights { outer, inner })
     }
 }
 
@@ -95,6 +102,24 @@ impl VFunction for NTupleMedium {
     fn into_weights(self) -> NTupleMediumWeights {
         self.weights
     }
+
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, mut f: G) {
+        for i in 0..4 {
+            let table = if i == 0 || i == 3 { 0 } else { 1 };
+
+            for tuple in &[state.row_at(i), state.column_at(i)] {
+                f(table, tuple.into_usize());
+                f(table, tuple.reversed().into_usize());
+            }
+        }
+    }
+
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32 {
+        match table {
+            0 => &mut self.weights.outer[index],
+            _ => &mut self.weights.inner[index],
+        }
+    }
 }
 
 #[cfg(test)]
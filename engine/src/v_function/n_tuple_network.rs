@@ -0,0 +1,262 @@
+use super::{VFunction, Weights};
+use crate::game::Board;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The longest tuple supported by the network
+const MAX_TUPLE_LEN: usize = 6;
+
+/// The 4 × 6-tuple network from Jaśkowski's "Mastering 2048 with Delayed Temporal Coherence
+/// Learning, Multi-Stage Weight Promotion, Redundant Encoding and Carousel Shaping"
+const DEFAULT_TUPLES: [[u8; 6]; 4] = [
+    [0, 1, 2, 3, 4, 5],
+    [4, 5, 6, 7, 8, 9],
+    [0, 1, 2, 4, 5, 6],
+    [4, 5, 6, 8, 9, 10],
+];
+
+/// Weights of a n-tuple network, along with the layout of its tuples
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NTupleNetworkWeights {
+    /// The cells covered by each tuple, numbered from 0 to 15 row by row
+    pub tuples: Vec<Vec<u8>>,
+    /// One lookup table per tuple, indexed by the tiles of the tuple. A layout without tables
+    /// starts with all its weights at zero.
+    #[serde(default)]
+    pub tables: Vec<Vec<f32>>,
+}
+
+impl NTupleNetworkWeights {
+    /// Creates zeroed weights for the given tuples
+    pub fn with_tuples(tuples: Vec<Vec<u8>>) -> NTupleNetworkWeights {
+        let tables = tuples
+            .iter()
+            .map(|tuple| vec![0.; table_size(tuple)])
+            .collect();
+        NTupleNetworkWeights { tuples, tables }
+    }
+}
+
+/// The default tuples without their tables, which take about 64MB per 6-tuple. The tables are
+/// only allocated when a network is built from the weights.
+impl Default for NTupleNetworkWeights {
+    fn default() -> Self {
+        NTupleNetworkWeights {
+            tuples: DEFAULT_TUPLES.iter().map(|tuple| tuple.to_vec()).collect(),
+            tables: Vec::new(),
+        }
+    }
+}
+
+impl Weights for NTupleNetworkWeights {
+    fn name() -> String {
+        "n_tuple_network".to_owned()
+    }
+
+    fn tables(&self) -> Vec<&[f32]> {
+        self.tables.iter().map(|table| table.as_slice()).collect()
+    }
+
+    fn from_tables(layout: serde_json::Value, tables: Vec<Vec<f32>>) -> Option<Self> {
+        let tuples = serde_json::from_value(layout.get("tuples")?.clone()).ok()?;
+        let weights = NTupleNetworkWeights { tuples, tables };
+
+        match weights.validate() {
+            Ok(()) if !weights.tables.is_empty() => Some(weights),
+            _ => None,
+        }
+    }
+
+    fn layout(&self) -> serde_json::Value {
+        json!({ "tuples": self.tuples })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for tuple in &self.tuples {
+            if tuple.is_empty() || tuple.len() > MAX_TUPLE_LEN {
+                return Err(format!("tuples must have 1 to {} cells", MAX_TUPLE_LEN));
+            }
+
+            if tuple.iter().any(|&cell| cell >= 16) {
+                return Err("tuple cells must be between 0 and 15".to_owned());
+            }
+        }
+
+        let tables_match = self.tables.len() == self.tuples.len()
+            && self
+                .tuples
+                .iter()
+                .zip(&self.tables)
+                .all(|(tuple, table)| table.len() == table_size(tuple));
+
+        if self.tables.is_empty() || tables_match {
+            Ok(())
+        } else {
+            Err("the tables don't match the tuples".to_owned())
+        }
+    }
+}
+
+/// A general n-tuple network.
+///
+/// Every tuple is applied to the 8 symmetries of the board, and the symmetric copies of a tuple
+/// share the same weights.
+#[derive(Debug, Clone)]
+pub struct NTupleNetwork {
+    weights: NTupleNetworkWeights,
+    /// Every symmetric copy of every tuple, as the index of its table and its cells
+    features: Vec<(usize, Vec<u8>)>,
+}
+
+impl NTupleNetwork {
+    fn index(state: Board, cells: &[u8]) -> usize {
+        let bits = state.into_u64();
+
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| (((bits >> (4 * cell)) & 0xF) as usize) << (4 * i))
+            .sum()
+    }
+}
+
+impl Default for NTupleNetwork {
+    fn default() -> Self {
+        NTupleNetwork::new(NTupleNetworkWeights::default())
+    }
+}
+
+impl VFunction for NTupleNetwork {
+    type Weights = NTupleNetworkWeights;
+
+    fn new(mut weights: NTupleNetworkWeights) -> Self {
+        if let Err(err) = weights.validate() {
+            panic!("invalid n-tuple network: {}", err);
+        }
+
+        if weights.tables.is_empty() {
+            weights = NTupleNetworkWeights::with_tuples(weights.tuples);
+        }
+
+        let features = weights
+            .tuples
+            .iter()
+            .enumerate()
+            .flat_map(|(table, tuple)| {
+                symmetric_tuples(tuple)
+                    .into_iter()
+                    .map(move |cells| (table, cells))
+            })
+            .collect();
+
+        NTupleNetwork { weights, features }
+    }
+
+    fn eval(&self, state: Board) -> f32 {
+        self.features
+            .iter()
+            .map(|(table, cells)| self.weights.tables[*table][Self::index(state, cells)])
+            .sum()
+    }
+
+    fn learn(&mut self, state: Board, delta: f32) {
+        let adjusted_delta = delta / (self.features.len() as f32);
+
+        for (table, cells) in &self.features {
+            self.weights.tables[*table][Self::index(state, cells)] += adjusted_delta;
+        }
+    }
+
+    fn into_weights(self) -> NTupleNetworkWeights {
+        self.weights
+    }
+
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, mut f: G) {
+        for (table, cells) in &self.features {
+            f(*table, Self::index(state, cells));
+        }
+    }
+
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32 {
+        &mut self.weights.tables[table][index]
+    }
+}
+
+fn table_size(tuple: &[u8]) -> usize {
+    1 << (4 * tuple.len())
+}
+
+/// The cells of the tuple in each of the 8 symmetries of the board, as given by
+/// `Board::symmetries`
+fn symmetric_tuples(tuple: &[u8]) -> Vec<Vec<u8>> {
+    // Every tile of this board is the number of its cell, so each of its symmetries shows where
+    // every cell goes
+    let numbered = Board::from_u64(0xFEDC_BA98_7654_3210);
+
+    numbered
+        .symmetries()
+        .iter()
+        .map(|symmetry| {
+            tuple
+                .iter()
+                .map(|&cell| {
+                    (0..16)
+                        .find(|&i| symmetry.at(i % 4, i / 4) == u64::from(cell))
+                        .expect("every cell is on the board") as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    const BOARD_1: Board =
+        Board::from_array([[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11], [12, 13, 14, 15]]);
+    /// `BOARD_1` rotated by 180 degrees
+    const BOARD_2: Board =
+        Board::from_array([[15, 14, 13, 12], [11, 10, 9, 8], [7, 6, 5, 4], [3, 2, 1, 0]]);
+
+    fn network() -> NTupleNetwork {
+        NTupleNetwork::new(NTupleNetworkWeights::with_tuples(vec![
+            vec![0, 1, 2],
+            vec![0, 4, 5, 6],
+        ]))
+    }
+
+    #[test]
+    fn symmetries_are_distinct() {
+        let mut cells = symmetric_tuples(&(0..16).collect::<Vec<u8>>());
+        cells.sort();
+        cells.dedup();
+
+        assert_eq!(cells.len(), 8);
+    }
+
+    #[test]
+    fn training_eval() {
+        let mut network = network();
+        network.learn(BOARD_1, 1.0);
+        assert_relative_eq!(network.eval(BOARD_1), 1.0);
+    }
+
+    #[test]
+    fn symmetric_generalization() {
+        let mut network = network();
+        network.learn(BOARD_1, 1.0);
+        assert_relative_eq!(network.eval(BOARD_2), network.eval(BOARD_1));
+    }
+
+    #[test]
+    fn invalid_layout() {
+        let weights = NTupleNetworkWeights {
+            tuples: vec![vec![0, 1, 16]],
+            tables: vec![],
+        };
+
+        assert!(weights.validate().is_err());
+    }
+}
@@ -1,12 +1,12 @@
-use super::{VFunction, Weights};
+use super::{weights_file, VFunction, Weights};
 use crate::game::Board;
 use lazy_static::lazy_static;
 use serde_derive::{Deserialize, Serialize};
 
 lazy_static! {
     static ref OPTIMIZED: NTupleSmallWeights =
-        serde_json::from_slice(include_bytes!("../../../networks/n_tuple_small.json"))
-            .expect("valid legacy weights");
+        weights_file::from_slice(include_bytes!("../../../networks/n_tuple_small.json"))
+            .expect("valid n tuple small weights");
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -17,8 +17,32 @@ pub struct NTupleSmallWeights {
 }
 
 impl Weights for NTupleSmallWeights {
-    fn optimized() -> NTupleSmallWeights {
-        OPTIMIZED.clone()
+    fn name() -> String {
+        "n_tuple_small".to_owned()
+    }
+
+    fn optimized() -> Option<NTupleSmallWeights> {
+        Some(OPTIMIZED.clone())
+    }
+
+    fn tables(&self) -> Vec<&[f32]> {
+        vec![&self.corner, &self.edge, &self.center]
+    }
+
+    fn from_tables(
+        _layout: serde_json::Value,
+        tables: Vec<Vec<f32>>,
+    ) -> Option<NTupleSmallWeights> {
+        match tables.as_slice() {
+            [corner, edge, center] if tables.iter().all(|table| table.len() == 16) => {
+                let mut weights = NTupleSmallWeights::default();
+                weights.corner.copy_from_slice(corner);
+                weights.edge.copy_from_slice(edge);
+                weights.center.copy_from_slice(center);
+                Some(weights)
+            }
+            _ => None,
+        }
     }
 }
 
@@ -81,4 +105,30 @@ impl VFunction for NTupleSmall {
     fn into_weights(self) -> NTupleSmallWeights {
         self.weights
     }
+
+    fn for_each_feature<G: FnMut(usize, usize)>(&self, state: Board, mut f: G) {
+        for i in &[0, 3] {
+            let row = state.row_at(*i);
+            f(0, row.tile_at(0) as usize);
+            f(1, row.tile_at(1) as usize);
+            f(1, row.tile_at(2) as usize);
+            f(0, row.tile_at(3) as usize);
+        }
+
+        for i in &[1, 2] {
+            let row = state.row_at(*i);
+            f(1, row.tile_at(0) as usize);
+            f(2, row.tile_at(1) as usize);
+            f(2, row.tile_at(2) as usize);
+            f(1, row.tile_at(3) as usize);
+        }
+    }
+
+    fn weight_mut(&mut self, table: usize, index: usize) -> &mut f32 {
+        match table {
+            0 => &mut self.weights.corner[index],
+            1 => &mut self.weights.edge[index],
+            _ => &mut self.weights.center[index],
+        }
+    }
 }
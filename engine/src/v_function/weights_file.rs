@@ -0,0 +1,182 @@
+use super::{binary_weights, Weights};
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The header of a weights file, which names the v-function the weights belong to
+#[derive(Deserialize)]
+struct Header {
+    v_function: String,
+}
+
+#[derive(Serialize)]
+struct WeightsFileRef<'a, W> {
+    v_function: String,
+    weights: &'a W,
+}
+
+#[derive(Deserialize)]
+struct WeightsFile<W> {
+    weights: W,
+}
+
+/// The encodings a weights file can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeightsFormat {
+    Json,
+    Binary,
+    /// The binary format with its weight tables compressed
+    CompressedBinary,
+}
+
+#[derive(Debug)]
+pub enum WeightsError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The weights file is malformed
+    Format(String),
+    /// The file holds the weights of another v-function
+    WrongVFunction {
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeightsError::Io(err) => write!(f, "could not access the weights file: {}", err),
+            WeightsError::Json(err) => write!(f, "invalid weights file: {}", err),
+            WeightsError::Format(err) => write!(f, "invalid weights file: {}", err),
+            WeightsError::WrongVFunction { expected, found } => write!(
+                f,
+                "expected weights for the {} v-function, found weights for {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for WeightsError {}
+
+impl From<io::Error> for WeightsError {
+    fn from(err: io::Error) -> WeightsError {
+        WeightsError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for WeightsError {
+    fn from(err: serde_json::Error) -> WeightsError {
+        WeightsError::Json(err)
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, WeightsError> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Loads a weights file in any of the supported formats
+pub fn load<W: Weights>(path: &Path) -> Result<W, WeightsError> {
+    from_slice(&read_file(path)?)
+}
+
+pub fn save<W: Weights>(
+    weights: &W,
+    path: &Path,
+    format: WeightsFormat,
+) -> Result<(), WeightsError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        WeightsFormat::Json => to_writer(weights, &mut writer)?,
+        WeightsFormat::Binary => binary_weights::to_writer(weights, &mut writer, false)?,
+        WeightsFormat::CompressedBinary => binary_weights::to_writer(weights, &mut writer, true)?,
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the name of the v-function a weights file belongs to, without loading the weights
+pub fn read_v_function(path: impl AsRef<Path>) -> Result<String, WeightsError> {
+    let bytes = read_file(path.as_ref())?;
+
+    if binary_weights::is_binary(&bytes) {
+        binary_weights::read_v_function(&bytes)
+    } else {
+        let header: Header = serde_json::from_slice(&bytes)?;
+        Ok(header.v_function)
+    }
+}
+
+/// Reads weights from the contents of a weights file, checking that they belong to `W`
+pub fn from_slice<W: Weights>(bytes: &[u8]) -> Result<W, WeightsError> {
+    let weights: W = if binary_weights::is_binary(bytes) {
+        binary_weights::from_slice(bytes)?
+    } else {
+        from_json_slice(bytes)?
+    };
+
+    weights.validate().map_err(WeightsError::Format)?;
+    Ok(weights)
+}
+
+fn from_json_slice<W: Weights>(bytes: &[u8]) -> Result<W, WeightsError> {
+    let header: Header = serde_json::from_slice(bytes)?;
+
+    if header.v_function != W::name() {
+        return Err(WeightsError::WrongVFunction {
+            expected: W::name(),
+            found: header.v_function,
+        });
+    }
+
+    let file: WeightsFile<W> = serde_json::from_slice(bytes)?;
+    Ok(file.weights)
+}
+
+pub fn to_writer<W: Weights>(weights: &W, writer: impl Write) -> Result<(), WeightsError> {
+    let file = WeightsFileRef {
+        v_function: W::name(),
+        weights,
+    };
+
+    serde_json::to_writer(writer, &file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v_function::{LegacyWeights, NTupleSmallWeights};
+
+    #[test]
+    fn round_trip() {
+        let weights = LegacyWeights::optimized().unwrap();
+        let mut bytes = Vec::new();
+        to_writer(&weights, &mut bytes).unwrap();
+
+        let loaded: LegacyWeights = from_slice(&bytes).unwrap();
+        assert_eq!(loaded.outer_pos_bonus, weights.outer_pos_bonus);
+        assert_eq!(loaded.inner_pos_bonus, weights.inner_pos_bonus);
+    }
+
+    #[test]
+    fn wrong_v_function() {
+        let mut bytes = Vec::new();
+        to_writer(&LegacyWeights::optimized().unwrap(), &mut bytes).unwrap();
+
+        match from_slice::<NTupleSmallWeights>(&bytes) {
+            Err(WeightsError::WrongVFunction { expected, found }) => {
+                assert_eq!(expected, "n_tuple_small");
+                assert_eq!(found, "legacy");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -4,51 +4,68 @@ from subprocess import run, Popen, PIPE
 from .__init__ import executable
 
 
+def read_checkpoint_run(path):
+    """Reads the JSON training config and state stored after the weights of a checkpoint"""
+    with open(path, "rb") as file:
+        file.seek(-8, os.SEEK_END)
+        run_len = int.from_bytes(file.read(8), "little")
+        file.seek(-8 - run_len, os.SEEK_END)
+        json_len = int.from_bytes(file.read(4), "little")
+        return json.loads(file.read(json_len))
+
+
 def train(v_function, num_games, alpha, benchmark_interval=5000):
-    process = Popen(
-        executable
-        + [
-            "train",
-            f"{num_games}",
+    checkpoint = f"networks/{v_function}.checkpoint"
+
+    # An interrupted run is resumed from its last checkpoint
+    if os.path.isfile(checkpoint):
+        start = ["--resume", checkpoint]
+    else:
+        start = [
             "-z",
             "--v_function",
             f"{v_function}",
             "--alpha",
             f"{alpha}",
             f"--benchmark-interval={benchmark_interval}",
+            "--checkpoint",
+            checkpoint,
+        ]
+
+    process = Popen(
+        executable
+        + ["train", f"{num_games}"]
+        + start
+        + [
             "--format",
             "json",
+            "--output",
+            f"networks/{v_function}.new.json",
         ],
         stdout=PIPE,
         encoding="UTF-8",
     )
 
-    games = []
-    training_scores = []
-    test_scores = []
-
     for line in process.stdout:
         message = json.loads(line)
+        print(f"Game {message['game']}, Average Score: {message['test_score']}")
+
+    if process.wait() != 0:
+        raise RuntimeError(f"training {v_function} failed")
 
-        if "game" in message:
-            games.append(message["game"])
-            training_scores.append(message["training_score"])
-            test_scores.append(message["test_score"])
+    # The checkpoint holds the history of the whole run, including the part before a resume
+    progress = read_checkpoint_run(checkpoint)["state"]["history"]
 
-            print(f"Game {message['game']}, Average Score: {message['test_score']}")
-        else:
-            if os.path.isfile(f"networks/{v_function}.json"):
-                os.replace(
-                    f"networks/{v_function}.json", f"networks/{v_function}.backup.json"
-                )
+    if os.path.isfile(f"networks/{v_function}.json"):
+        os.replace(f"networks/{v_function}.json", f"networks/{v_function}.backup.json")
 
-            with open(f"networks/{v_function}.json", "w") as file:
-                file.write(line)
+    os.replace(f"networks/{v_function}.new.json", f"networks/{v_function}.json")
+    os.remove(checkpoint)
 
     history = {
-        "games": games,
-        "training_scores": training_scores,
-        "test_scores": test_scores,
+        "games": [message["game"] for message in progress],
+        "training_scores": [message["training_score"] for message in progress],
+        "test_scores": [message["test_score"] for message in progress],
     }
 
     with open(f"networks/{v_function}.training.json", "w") as file: